@@ -0,0 +1,346 @@
+use crate::backup::atomic_write;
+use crate::lock::FolderLock;
+use crate::model::UserProfile;
+use anyhow::{anyhow, Result};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::Path;
+
+/// Accounts with hashed passwords, in the data folder.
+/// Not part of the backups, so that restoring data does not bring back an old password.
+pub const ACCOUNTS_FILE: &str = "hours_accounts.json";
+/// Name of the admin account created by the first-run setup.
+pub const ADMIN: &str = "admin";
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Hashes a password or PIN with argon2 and a random salt, in PHC string format.
+pub fn hash_secret(secret: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map_err(|e| anyhow!("Error hashing: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// True if `secret` matches a hash made by [hash_secret]; false for an empty or invalid hash.
+pub fn verify_secret(secret: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default()
+            .verify_password(secret.as_bytes(), &hash)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// A PIN is 4 to 8 digits.
+pub fn validate_pin(pin: &str) -> Result<()> {
+    if pin.len() < 4 || pin.len() > 8 || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(anyhow!("A PIN has to be 4 to 8 digits"));
+    }
+    Ok(())
+}
+
+pub fn validate_password(password: &str) -> Result<()> {
+    if password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(anyhow!(
+            "The password needs at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// What an account may do.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Everything, including settings, backups and accounts.
+    #[default]
+    Admin,
+    /// Edits the users of one team and their records.
+    Manager,
+    /// Sees all reports and the audit history, but can not change anything.
+    Auditor,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Admin, Role::Manager, Role::Auditor];
+
+    pub fn parse(role: &str) -> Result<Role> {
+        Role::ALL
+            .into_iter()
+            .find(|r| r.to_string() == role.to_lowercase())
+            .ok_or_else(|| anyhow!("Unknown role {}", role))
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Role::Admin => "admin",
+            Role::Manager => "manager",
+            Role::Auditor => "auditor",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Account {
+    pub name: String,
+    /// Argon2 hash, see [hash_secret].
+    pub password_hash: String,
+    #[serde(default)]
+    pub role: Role,
+    /// Team of a manager; empty for other roles.
+    #[serde(default)]
+    pub team: String,
+}
+
+/// The logged in account, checked by every change of [crate::model::HoursData] an account makes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Access {
+    pub name: String,
+    pub role: Role,
+    pub team: String,
+}
+
+impl From<&Account> for Access {
+    fn from(account: &Account) -> Self {
+        Self {
+            name: account.name.clone(),
+            role: account.role,
+            team: account.team.clone(),
+        }
+    }
+}
+
+impl Access {
+    pub fn is_admin(&self) -> bool {
+        self.role == Role::Admin
+    }
+
+    /// True for roles that edit users: admins and managers.
+    pub fn can_edit(&self) -> bool {
+        self.role != Role::Auditor
+    }
+
+    pub fn can_edit_user(&self, user: &UserProfile) -> bool {
+        match self.role {
+            Role::Admin => true,
+            Role::Manager => !self.team.is_empty() && user.team == self.team,
+            Role::Auditor => false,
+        }
+    }
+
+    /// True for roles that see the audit history.
+    pub fn can_audit(&self) -> bool {
+        self.role != Role::Manager
+    }
+
+    pub fn require_admin(&self) -> Result<()> {
+        if self.is_admin() {
+            Ok(())
+        } else {
+            Err(anyhow!("{} is not an admin", self.name))
+        }
+    }
+
+    pub fn require_edit(&self) -> Result<()> {
+        if self.can_edit() {
+            Ok(())
+        } else {
+            Err(anyhow!("{} can not change anything", self.name))
+        }
+    }
+
+    pub fn require_edit_user(&self, user: &UserProfile) -> Result<()> {
+        if self.can_edit_user(user) {
+            Ok(())
+        } else {
+            Err(anyhow!("{} can not change {}", self.name, user.name))
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Accounts {
+    pub accounts: Vec<Account>,
+}
+
+impl Accounts {
+    /// Reads the accounts of the data folder; no file means no accounts yet.
+    pub fn load<P: AsRef<Path>>(folder: P) -> Result<Accounts> {
+        let path = folder.as_ref().join(ACCOUNTS_FILE);
+        if !path.exists() {
+            return Ok(Accounts::default());
+        }
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, folder: P) -> Result<()> {
+        let _lock = FolderLock::acquire(&folder)?;
+        atomic_write(
+            folder.as_ref().join(ACCOUNTS_FILE),
+            serde_json::to_string_pretty(self)?,
+        )
+    }
+
+    /// False until the first-run setup has created an account.
+    pub fn is_set_up(&self) -> bool {
+        !self.accounts.is_empty()
+    }
+
+    pub fn account(&self, name: &str) -> Option<&Account> {
+        self.accounts.iter().find(|a| a.name == name)
+    }
+
+    pub fn verify(&self, name: &str, password: &str) -> bool {
+        self.account(name)
+            .map(|a| verify_secret(password, &a.password_hash))
+            .unwrap_or(false)
+    }
+
+    /// Sets the password of the account, creating an admin account if needed.
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<()> {
+        validate_password(password)?;
+        let password_hash = hash_secret(password)?;
+        match self.accounts.iter_mut().find(|a| a.name == name) {
+            Some(account) => account.password_hash = password_hash,
+            None => self.accounts.push(Account {
+                name: name.to_string(),
+                password_hash,
+                role: Role::Admin,
+                team: String::new(),
+            }),
+        }
+        Ok(())
+    }
+
+    pub fn add(&mut self, name: &str, role: Role, team: &str, password: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("The account name can not be empty"));
+        }
+        if self.account(name).is_some() {
+            return Err(anyhow!("There is already an account {}", name));
+        }
+        if role == Role::Manager && team.trim().is_empty() {
+            return Err(anyhow!("A manager needs a team"));
+        }
+        self.set_password(name, password)?;
+        self.set_role(name, role, team)
+    }
+
+    pub fn set_role(&mut self, name: &str, role: Role, team: &str) -> Result<()> {
+        let account = self
+            .accounts
+            .iter_mut()
+            .find(|a| a.name == name)
+            .ok_or_else(|| anyhow!("No account {}", name))?;
+        account.role = role;
+        account.team = if role == Role::Manager {
+            team.trim().to_string()
+        } else {
+            String::new()
+        };
+        self.require_admin_left()
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        self.accounts.retain(|a| a.name != name);
+        self.require_admin_left()
+    }
+
+    fn require_admin_left(&self) -> Result<()> {
+        if self.accounts.iter().any(|a| a.role == Role::Admin) {
+            Ok(())
+        } else {
+            Err(anyhow!("At least one admin account is needed"))
+        }
+    }
+}
+
+/// First-run setup: creates the admin account of the data folder.
+/// Fails if the folder already has accounts.
+pub fn setup<P: AsRef<Path>>(folder: P, password: &str) -> Result<()> {
+    let mut accounts = Accounts::load(&folder)?;
+    if accounts.is_set_up() {
+        return Err(anyhow!("The admin account is already set up"));
+    }
+    accounts.set_password(ADMIN, password)?;
+    accounts.save(folder)
+}
+
+/// Changes the password of an account after checking the current one.
+pub fn change_password<P: AsRef<Path>>(
+    folder: P,
+    name: &str,
+    current: &str,
+    new: &str,
+) -> Result<()> {
+    let mut accounts = Accounts::load(&folder)?;
+    if !accounts.verify(name, current) {
+        return Err(anyhow!("The current password is wrong"));
+    }
+    accounts.set_password(name, new)?;
+    accounts.save(folder)
+}
+
+#[derive(Debug, Clone, Default)]
+struct Failures {
+    count: u32,
+    locked_until: Option<DateTime<Local>>,
+}
+
+/// Counts failed attempts per key (a user id or account name) and locks the key
+/// for a while after too many failures in a row.
+#[derive(Debug, Clone, Default)]
+pub struct Throttle {
+    failures: HashMap<String, Failures>,
+}
+
+impl Throttle {
+    /// Remaining lockout of the key, if it is locked.
+    pub fn locked_for(&self, key: &str) -> Option<Duration> {
+        let until = self.failures.get(key)?.locked_until?;
+        let remaining = until - Local::now();
+        if remaining > Duration::zero() {
+            Some(remaining)
+        } else {
+            None
+        }
+    }
+
+    /// Fails with a message if the key is locked.
+    pub fn check(&self, key: &str) -> Result<()> {
+        match self.locked_for(key) {
+            Some(remaining) => Err(anyhow!(
+                "Too many failed attempts, try again in {} seconds",
+                remaining.num_seconds() + 1
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Records a failed attempt; the `max_failures`-th failure in a row locks the key.
+    pub fn fail(&mut self, key: &str, max_failures: u32, lockout_minutes: i64) {
+        let failures = self.failures.entry(key.to_string()).or_default();
+        failures.count += 1;
+        if max_failures > 0 && failures.count >= max_failures {
+            failures.count = 0;
+            failures.locked_until = Some(Local::now() + Duration::minutes(lockout_minutes));
+        }
+    }
+
+    pub fn succeed(&mut self, key: &str) {
+        self.failures.remove(key);
+    }
+}
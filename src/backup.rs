@@ -0,0 +1,229 @@
+use crate::journal::JOURNAL_FILE;
+use crate::lock::FolderLock;
+use crate::migration::{DATAFRAME_FILE, META_FILE, NAMES_FILE, USERS_FILE};
+use crate::storage::SQLITE_FILE;
+use anyhow::{anyhow, Result};
+use chrono::{Local, NaiveDateTime};
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+pub const BACKUP_FOLDER: &str = "backups";
+const TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// Files of a data folder that are copied into a backup.
+pub const DATA_FILES: [&str; 6] = [
+    DATAFRAME_FILE,
+    NAMES_FILE,
+    USERS_FILE,
+    META_FILE,
+    JOURNAL_FILE,
+    SQLITE_FILE,
+];
+
+/// Writes `contents` to a temporary file next to `path`, syncs it and renames it over `path`,
+/// so that a crash leaves either the old or the new file, never a truncated one.
+pub fn atomic_write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<()> {
+    let path = path.as_ref();
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(contents.as_ref())?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&tmp_path, path)?;
+    #[cfg(unix)]
+    if let Some(parent) = path.parent() {
+        File::open(parent)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// How many backups are kept and how often a new one is taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupPolicy {
+    pub count: usize,
+    pub interval_minutes: i64,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            count: 20,
+            interval_minutes: 60,
+        }
+    }
+}
+
+/// A timestamped copy of the data files in the `backups` subfolder of the data folder.
+/// Backups taken in the same second get a counter, e.g. `20230403-080000-1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Backup {
+    pub name: String,
+    pub path: PathBuf,
+    pub time: NaiveDateTime,
+}
+
+impl Backup {
+    pub fn time_text(&self) -> String {
+        self.time.format("%Y/%m/%d %H:%M:%S").to_string()
+    }
+}
+
+/// Time and counter of a backup name without prefix; None for other names.
+fn parse_name(name: &str) -> Option<(NaiveDateTime, u32)> {
+    let (time, counter) = match name.get(15..) {
+        Some("") => (name, 0),
+        Some(rest) => (&name[..15], rest.strip_prefix('-')?.parse().ok()?),
+        None => return None,
+    };
+    Some((
+        NaiveDateTime::parse_from_str(time, TIME_FORMAT).ok()?,
+        counter,
+    ))
+}
+
+/// Lists the backups of a data folder, newest first.
+/// Backups with a name prefix, see [create], are not listed and never rotated.
+pub fn list<P: AsRef<Path>>(folder: P) -> Result<Vec<Backup>> {
+    let backup_folder = folder.as_ref().join(BACKUP_FOLDER);
+    if !backup_folder.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(&backup_folder)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if let Some((time, _)) = parse_name(&name) {
+            if entry.path().is_dir() {
+                backups.push(Backup {
+                    name,
+                    path: entry.path(),
+                    time,
+                });
+            }
+        }
+    }
+    backups.sort_by_key(|b| std::cmp::Reverse(parse_name(&b.name)));
+    Ok(backups)
+}
+
+/// Copies the current data files into a new backup named `<prefix><time>`.
+/// Backups without prefix are the regular ones that can be restored; a prefix such as
+/// `broken-` marks copies kept for other reasons.
+pub fn create<P: AsRef<Path>>(folder: P, prefix: &str) -> Result<Backup> {
+    let folder = folder.as_ref();
+    let now = Local::now().naive_local();
+    let time = format!("{}{}", prefix, now.format(TIME_FORMAT));
+    std::fs::create_dir_all(folder.join(BACKUP_FOLDER))?;
+    let mut name = time.clone();
+    let mut counter = 0;
+    let path = loop {
+        let path = folder.join(BACKUP_FOLDER).join(&name);
+        match std::fs::create_dir(&path) {
+            Ok(()) => break path,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                counter += 1;
+                name = format!("{}-{}", time, counter);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+    for file in DATA_FILES {
+        let source = folder.join(file);
+        if source.exists() {
+            std::fs::copy(&source, path.join(file))?;
+        }
+    }
+    Ok(Backup {
+        name,
+        path,
+        time: now,
+    })
+}
+
+/// Removes all but the newest `count` backups.
+pub fn rotate<P: AsRef<Path>>(folder: P, count: usize) -> Result<()> {
+    for backup in list(folder)?.into_iter().skip(count) {
+        std::fs::remove_dir_all(&backup.path)?;
+    }
+    Ok(())
+}
+
+/// Takes a backup if the newest one is older than the policy interval, then rotates.
+/// Nothing is done if the folder has no data yet.
+pub fn backup_if_due<P: AsRef<Path>>(folder: P, policy: &BackupPolicy) -> Result<()> {
+    let folder = folder.as_ref();
+    if policy.count == 0 || !DATA_FILES.iter().any(|file| folder.join(file).exists()) {
+        return Ok(());
+    }
+    let due = match list(folder)?.first() {
+        Some(newest) => {
+            let age = Local::now().naive_local() - newest.time;
+            age.num_minutes() >= policy.interval_minutes
+        }
+        None => true,
+    };
+    if due {
+        create(folder, "")?;
+        rotate(folder, policy.count)?;
+    }
+    Ok(())
+}
+
+/// Replaces the data files with the ones from the named backup.
+/// The current state is backed up first, so a restore can be undone.
+pub fn restore<P: AsRef<Path>>(folder: P, name: &str) -> Result<()> {
+    let folder = folder.as_ref();
+    let _lock = FolderLock::acquire(folder)?;
+    let backup = list(folder)?
+        .into_iter()
+        .find(|b| b.name == name)
+        .ok_or_else(|| anyhow!("Backup {} not found", name))?;
+    create(folder, "")?;
+    for file in DATA_FILES {
+        let source = backup.path.join(file);
+        let target = folder.join(file);
+        if source.exists() {
+            atomic_write(&target, std::fs::read(&source)?)?;
+        } else if target.exists() {
+            std::fs::remove_file(&target)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backups_in_the_same_second_get_unique_names() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(DATAFRAME_FILE), "first").unwrap();
+        let first = create(dir.path(), "").unwrap();
+        std::fs::write(dir.path().join(DATAFRAME_FILE), "second").unwrap();
+        let second = create(dir.path(), "").unwrap();
+        let broken = create(dir.path(), "broken-").unwrap();
+        assert_ne!(first.name, second.name);
+        assert!(broken.name.starts_with("broken-"));
+
+        // the restore is backed up first, even within the same second
+        restore(dir.path(), &first.name).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(DATAFRAME_FILE)).unwrap(),
+            "first"
+        );
+        let backups = list(dir.path()).unwrap();
+        assert_eq!(backups.len(), 3);
+        assert!(!backups.iter().any(|b| b.name == broken.name));
+        assert_eq!(
+            std::fs::read_to_string(backups[0].path.join(DATAFRAME_FILE)).unwrap(),
+            "second"
+        );
+        assert_eq!(backups[2].name, first.name);
+    }
+}
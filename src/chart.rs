@@ -0,0 +1,203 @@
+use crate::model::Hours;
+
+use std::fmt::Write;
+
+const WIDTH: f64 = 600.0;
+const HEIGHT: f64 = 200.0;
+const LEFT: f64 = 40.0;
+const BOTTOM: f64 = 20.0;
+const TOP: f64 = 10.0;
+const LEGEND_ROW: f64 = 18.0;
+const LEGEND_COLUMNS: usize = 4;
+const MAX_LABELS: usize = 12;
+const COLORS: [&str; 8] = [
+    "#4E79A7", "#F28E2B", "#59A14F", "#E15759", "#76B7B2", "#EDC948", "#B07AA1", "#9C755F",
+];
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Value axis from zero to a whole number of hours, with at most five grid lines.
+struct Scale {
+    max: f64,
+    step: f64,
+}
+
+impl Scale {
+    fn new(max_hours: f64) -> Self {
+        let max = max_hours.ceil().max(1.0);
+        let step = (max / 5.0).ceil();
+        Self {
+            max: (max / step).ceil() * step,
+            step,
+        }
+    }
+    fn y(&self, hours: f64) -> f64 {
+        HEIGHT - BOTTOM - (hours / self.max) * (HEIGHT - BOTTOM - TOP)
+    }
+}
+
+/// Horizontal position of slot `i` of `n`: left edge and width.
+fn slot(i: usize, n: usize) -> (f64, f64) {
+    let width = (WIDTH - LEFT) / n.max(1) as f64;
+    (LEFT + i as f64 * width, width)
+}
+
+/// Svg element with grid lines and labels; `body` draws the data.
+fn frame(labels: &[String], scale: &Scale, extra_height: f64, body: &str) -> String {
+    let mut svg = String::new();
+    write!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-size="10" fill="currentColor">"#,
+        w = WIDTH,
+        h = HEIGHT + extra_height
+    )
+    .unwrap();
+    let mut value = 0.0;
+    while value <= scale.max {
+        let y = scale.y(value);
+        write!(
+            svg,
+            r##"<line x1="{LEFT}" y1="{y:.1}" x2="{WIDTH}" y2="{y:.1}" stroke="#6D6D6D" stroke-width="0.5"/><text x="{x}" y="{ty:.1}" text-anchor="end">{value}h</text>"##,
+            x = LEFT - 4.0,
+            ty = y + 3.0
+        )
+        .unwrap();
+        value += scale.step;
+    }
+    let every = labels.len().div_ceil(MAX_LABELS);
+    for (i, label) in labels.iter().enumerate() {
+        if every > 0 && i % every == 0 {
+            let (x, width) = slot(i, labels.len());
+            write!(
+                svg,
+                r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+                x + width / 2.0,
+                HEIGHT - 6.0,
+                escape(label)
+            )
+            .unwrap();
+        }
+    }
+    svg.push_str(body);
+    svg.push_str("</svg>");
+    svg
+}
+
+fn rect(svg: &mut String, x: f64, y: f64, width: f64, height: f64, color: &str, title: &str) {
+    write!(
+        svg,
+        r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"><title>{}</title></rect>"#,
+        x,
+        y,
+        width,
+        height,
+        color,
+        escape(title)
+    )
+    .unwrap();
+}
+
+/// Bars of the hours for each label, e.g. per day of a month.
+pub fn bar_chart(labels: &[String], values: &[Hours]) -> String {
+    let scale = Scale::new(values.iter().map(|h| h.as_hours()).fold(0.0, f64::max));
+    let mut body = String::new();
+    for (i, (label, value)) in labels.iter().zip(values.iter()).enumerate() {
+        if value.seconds() <= 0 {
+            continue;
+        }
+        let (x, width) = slot(i, labels.len());
+        let y = scale.y(value.as_hours());
+        let title = format!("{}: {}", label, value);
+        rect(&mut body, x + width * 0.15, y, width * 0.7, scale.y(0.0) - y, COLORS[0], &title);
+    }
+    frame(labels, &scale, 0.0, &body)
+}
+
+/// Line through the hours for each label, e.g. per month of a year.
+pub fn line_chart(labels: &[String], values: &[Hours]) -> String {
+    let scale = Scale::new(values.iter().map(|h| h.as_hours()).fold(0.0, f64::max));
+    let points = values
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let (x, width) = slot(i, values.len());
+            (x + width / 2.0, scale.y(value.as_hours()))
+        })
+        .collect::<Vec<_>>();
+    let mut body = String::new();
+    let line = points
+        .iter()
+        .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ");
+    write!(
+        body,
+        r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+        line, COLORS[0]
+    )
+    .unwrap();
+    for ((x, y), (label, value)) in points.iter().zip(labels.iter().zip(values.iter())) {
+        write!(
+            body,
+            r#"<circle cx="{:.1}" cy="{:.1}" r="3" fill="{}"><title>{}</title></circle>"#,
+            x,
+            y,
+            COLORS[0],
+            escape(&format!("{}: {}", label, value))
+        )
+        .unwrap();
+    }
+    frame(labels, &scale, 0.0, &body)
+}
+
+/// Bars for each label stacked from the values of several series, e.g. users of a team per day,
+/// with a legend of the series names below.
+pub fn stacked_bar_chart(labels: &[String], series: &[(String, Vec<Hours>)]) -> String {
+    let totals = (0..labels.len())
+        .map(|i| {
+            series
+                .iter()
+                .filter_map(|(_, values)| values.get(i))
+                .map(|h| h.as_hours().max(0.0))
+                .sum::<f64>()
+        })
+        .collect::<Vec<_>>();
+    let scale = Scale::new(totals.iter().copied().fold(0.0, f64::max));
+    let mut body = String::new();
+    for (i, label) in labels.iter().enumerate() {
+        let (x, width) = slot(i, labels.len());
+        let mut bottom = 0.0;
+        for (j, (name, values)) in series.iter().enumerate() {
+            let value = values.get(i).copied().unwrap_or_default();
+            if value.seconds() <= 0 {
+                continue;
+            }
+            let top = bottom + value.as_hours();
+            let title = format!("{} {}: {}", name, label, value);
+            let color = COLORS[j % COLORS.len()];
+            rect(&mut body, x + width * 0.15, scale.y(top), width * 0.7, scale.y(bottom) - scale.y(top), color, &title);
+            bottom = top;
+        }
+    }
+    let column = (WIDTH - LEFT) / LEGEND_COLUMNS as f64;
+    for (j, (name, _)) in series.iter().enumerate() {
+        let x = LEFT + (j % LEGEND_COLUMNS) as f64 * column;
+        let y = HEIGHT + (j / LEGEND_COLUMNS) as f64 * LEGEND_ROW + 4.0;
+        rect(&mut body, x, y, 10.0, 10.0, COLORS[j % COLORS.len()], name);
+        write!(
+            body,
+            r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+            x + 14.0,
+            y + 9.0,
+            escape(name)
+        )
+        .unwrap();
+    }
+    let legend_rows = series.len().div_ceil(LEGEND_COLUMNS);
+    frame(labels, &scale, legend_rows as f64 * LEGEND_ROW + 4.0, &body)
+}
@@ -0,0 +1,155 @@
+use crate::model::{Hours, HoursDataFrame, HoursRecord};
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, NaiveDateTime};
+
+/// A problem of a single record found by [check].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// The start or end time can not be read.
+    InvalidTime,
+    EndBeforeStart,
+    /// The record ends after the next record of the same user starts.
+    Overlap { next: isize },
+    /// The record has no end, but the user has a later record.
+    Open { next: isize },
+    /// The hours override can not be read.
+    InvalidHours,
+    /// The hours override is not stored as decimal hours, but can be read as typed input, e.g. `7:30`.
+    HoursNotDecimal,
+    /// The record lasts longer than a shift and has no hours override,
+    /// e.g. a session that was ended days after it was forgotten.
+    TooLong,
+    /// A stale record that was closed at its start and has no hours override yet,
+    /// see [crate::model::HoursData::close_stale].
+    ClosedWithoutHours,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    /// Index of the record, see [HoursDataFrame::new_index].
+    pub index: isize,
+    pub user_id: u64,
+    /// Date of the record as shown in the user view.
+    pub date: String,
+    pub problem: Problem,
+}
+
+impl Issue {
+    pub fn describe(&self) -> String {
+        match self.problem {
+            Problem::InvalidTime => "Start or end time can not be read".to_string(),
+            Problem::EndBeforeStart => "Ends before it starts".to_string(),
+            Problem::Overlap { next } => format!("Overlaps the next record {}", next),
+            Problem::Open { next } => format!("Not ended, although record {} follows", next),
+            Problem::InvalidHours => "Hours can not be read".to_string(),
+            Problem::HoursNotDecimal => "Hours are not stored as decimal hours".to_string(),
+            Problem::TooLong => "Lasts longer than a shift, enter the hours worked".to_string(),
+            Problem::ClosedWithoutHours => {
+                "Closed without hours, enter the hours worked".to_string()
+            }
+        }
+    }
+
+    /// Label of the fix; None if the record has to be corrected by hand.
+    pub fn fix_label(&self) -> Option<&'static str> {
+        match self.problem {
+            Problem::InvalidTime | Problem::TooLong | Problem::ClosedWithoutHours => None,
+            Problem::EndBeforeStart => Some("Swap start and end"),
+            Problem::Overlap { .. } | Problem::Open { .. } => Some("End at the next start"),
+            Problem::InvalidHours => Some("Clear hours"),
+            Problem::HoursNotDecimal => Some("Store hours as decimal"),
+        }
+    }
+
+    /// The record with the problem fixed.
+    pub fn fix(&self, dataframe: &HoursDataFrame) -> Result<HoursRecord> {
+        let mut record = dataframe
+            .record(self.index)
+            .cloned()
+            .ok_or_else(|| anyhow!("No record {}", self.index))?;
+        match self.problem {
+            Problem::InvalidTime | Problem::TooLong | Problem::ClosedWithoutHours => {
+                return Err(anyhow!("Record {} has to be corrected by hand", self.index))
+            }
+            Problem::EndBeforeStart => {
+                std::mem::swap(&mut record.start, &mut record.end);
+                let start = record.start_dt()?;
+                record.year = start.year();
+                record.month = start.month();
+            }
+            Problem::Overlap { next } | Problem::Open { next } => {
+                record.end = dataframe
+                    .record(next)
+                    .map(|r| r.start.clone())
+                    .ok_or_else(|| anyhow!("No record {}", next))?;
+            }
+            Problem::InvalidHours => record.hours = "".to_string(),
+            Problem::HoursNotDecimal => record.hours = Hours::normalize(&record.hours)?,
+        }
+        Ok(record)
+    }
+}
+
+/// Finds records with unreadable, inconsistent or too long times, records closed without hours
+/// and unreadable hours overrides.
+/// Records are compared with the next record of the same user by start time.
+pub fn check(dataframe: &HoursDataFrame) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut records: Vec<(&HoursRecord, NaiveDateTime)> = Vec::new();
+    for record in dataframe.data.iter() {
+        let issue = |problem| Issue {
+            index: record.index,
+            user_id: record.user_id,
+            date: record.date(),
+            problem,
+        };
+        if !record.hours.trim().is_empty() && Hours::from_decimal_str(&record.hours).is_err() {
+            if Hours::normalize(&record.hours).is_ok() {
+                issues.push(issue(Problem::HoursNotDecimal));
+            } else {
+                issues.push(issue(Problem::InvalidHours));
+            }
+        }
+        let start = match record.start_dt() {
+            Ok(start) => start,
+            Err(_) => {
+                issues.push(issue(Problem::InvalidTime));
+                continue;
+            }
+        };
+        if !record.end.is_empty() {
+            match record.end_dt() {
+                Ok(end) if end < start => issues.push(issue(Problem::EndBeforeStart)),
+                Ok(_) if record.is_too_long() => issues.push(issue(Problem::TooLong)),
+                Ok(end) if end == start && record.hours.trim().is_empty() => {
+                    issues.push(issue(Problem::ClosedWithoutHours))
+                }
+                Ok(_) => {}
+                Err(_) => issues.push(issue(Problem::InvalidTime)),
+            }
+        }
+        records.push((record, start));
+    }
+
+    records.sort_by_key(|(r, start)| (r.user_id, *start));
+    for pair in records.windows(2) {
+        let ((record, start), (next, next_start)) = (pair[0], pair[1]);
+        if record.user_id != next.user_id {
+            continue;
+        }
+        let issue = |problem| Issue {
+            index: record.index,
+            user_id: record.user_id,
+            date: record.date(),
+            problem,
+        };
+        if record.end.is_empty() {
+            issues.push(issue(Problem::Open { next: next.index }));
+        } else if let Ok(end) = record.end_dt() {
+            if end > next_start && end >= start {
+                issues.push(issue(Problem::Overlap { next: next.index }));
+            }
+        }
+    }
+    issues
+}
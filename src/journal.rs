@@ -0,0 +1,343 @@
+use crate::model::{HoursData, HoursRecord, UserProfile};
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+/// Events since the last snapshot, one json object per line.
+pub const JOURNAL_FILE: &str = "hours_journal.jsonl";
+/// Events moved out of the journal by compaction; kept as history, not needed for loading.
+pub const HISTORY_FILE: &str = "hours_history.jsonl";
+/// Number of journal events after which the snapshot is rewritten.
+pub const COMPACT_AFTER: usize = 200;
+
+/// A change of [HoursData]. Records are identified by their unique `index`, users by their `id`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event")]
+pub enum Event {
+    Started { record: HoursRecord },
+    Ended { record: HoursRecord },
+    Edited { record: HoursRecord },
+    UserAdded { user: UserProfile },
+    UserUpdated { user: UserProfile },
+    /// Renames the user and every record linked to it.
+    UserRenamed { id: u64, from: String, to: String },
+    UserRemoved { id: u64 },
+    /// Links the records of `from_user_id` with `from_name` to another user.
+    RecordsReassigned {
+        from_user_id: u64,
+        from_name: String,
+        user_id: u64,
+        name: String,
+    },
+    UsersReordered { ids: Vec<u64> },
+}
+
+impl Event {
+    pub fn apply(&self, data: &mut HoursData) {
+        match self {
+            Event::Started { record } | Event::Ended { record } | Event::Edited { record } => {
+                if let Some(r) = data
+                    .dataframe
+                    .data
+                    .iter_mut()
+                    .find(|r| r.index == record.index)
+                {
+                    *r = record.clone();
+                } else {
+                    data.dataframe.use_index(record.index);
+                    data.dataframe.data.push(record.clone());
+                }
+            }
+            Event::UserAdded { user } | Event::UserUpdated { user } => {
+                if let Some(u) = data.users.iter_mut().find(|u| u.id == user.id) {
+                    *u = user.clone();
+                } else {
+                    data.users.push(user.clone());
+                }
+            }
+            Event::UserRenamed { id, to, .. } => {
+                if let Some(u) = data.users.iter_mut().find(|u| u.id == *id) {
+                    u.name = to.clone();
+                }
+                for record in data.dataframe.data.iter_mut() {
+                    if record.user_id == *id {
+                        record.name = to.clone();
+                    }
+                }
+            }
+            Event::RecordsReassigned {
+                from_user_id,
+                from_name,
+                user_id,
+                name,
+            } => {
+                for record in data.dataframe.data.iter_mut() {
+                    if record.user_id == *from_user_id && record.name == *from_name {
+                        record.user_id = *user_id;
+                        record.name = name.clone();
+                    }
+                }
+            }
+            Event::UserRemoved { id } => {
+                data.users.retain(|u| u.id != *id);
+            }
+            Event::UsersReordered { ids } => {
+                data.users
+                    .sort_by_key(|u| ids.iter().position(|id| *id == u.id).unwrap_or(ids.len()));
+            }
+        }
+    }
+}
+
+impl Event {
+    /// One line description for the audit history.
+    pub fn describe(&self) -> String {
+        match self {
+            Event::Started { record } => format!("{} started {}", record.name, record.start),
+            Event::Ended { record } => format!("{} ended {}", record.name, record.end),
+            Event::Edited { record } => format!(
+                "Hours of {} on {} set to {}",
+                record.name,
+                record.date(),
+                record.hours
+            ),
+            Event::UserAdded { user } => format!("User {} added", user.name),
+            Event::UserUpdated { user } => format!("Profile of {} changed", user.name),
+            Event::UserRenamed { from, to, .. } => format!("User {} renamed to {}", from, to),
+            Event::UserRemoved { id } => format!("User {} removed", id),
+            Event::RecordsReassigned {
+                from_name, name, ..
+            } => format!("Records of {} moved to {}", from_name, name),
+            Event::UsersReordered { .. } => "Users reordered".to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub time: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+impl JournalEntry {
+    pub fn new(event: Event) -> Self {
+        Self {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            event,
+        }
+    }
+}
+
+/// Reads a journal or history file; a missing file is an empty journal.
+/// A last line that can not be read, left by a write that was interrupted, is ignored;
+/// any other line that can not be read is an error.
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<JournalEntry>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let content = std::fs::read(path)?;
+    let text = String::from_utf8_lossy(&content);
+    let lines: Vec<&str> = text.lines().collect();
+    let mut entries = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(e) if i + 1 == lines.len() => {
+                println!("Ignoring the incomplete last line of {:?}: {}", path, e)
+            }
+            Err(e) => return Err(anyhow!("Error in {:?} line {}: {}", path, i + 1, e)),
+        }
+    }
+    Ok(entries)
+}
+
+/// Like [read], but skips lines that can not be read, e.g. events of older versions
+/// kept in the history file.
+pub fn read_lenient<P: AsRef<Path>>(path: P) -> Result<Vec<JournalEntry>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        if let Ok(entry) = serde_json::from_str(&line?) {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Appends entries and syncs the file, so that an event is durable once this returns.
+/// A last line without newline is ended first if it can be read and removed otherwise,
+/// as [read] ignores it, so that the new entries start on a line of their own.
+pub fn append<P: AsRef<Path>>(path: P, entries: &[JournalEntry]) -> Result<()> {
+    let path = path.as_ref();
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let content = std::fs::read(path)?;
+    if !content.is_empty() && !content.ends_with(b"\n") {
+        let start = content
+            .iter()
+            .rposition(|b| *b == b'\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        if serde_json::from_slice::<JournalEntry>(&content[start..]).is_ok() {
+            writeln!(file)?;
+        } else {
+            println!("Removing the incomplete last line of {:?}", path);
+            file.set_len(start as u64)?;
+        }
+    }
+    for entry in entries {
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+    }
+    file.sync_data()?;
+    Ok(())
+}
+
+/// Moves the journal of the data folder to the end of the history file.
+/// To be called after a snapshot containing all the events has been written.
+/// The lines are moved as they are, so that even unreadable entries are kept.
+pub fn archive<P: AsRef<Path>>(folder: P) -> Result<()> {
+    let journal_path = folder.as_ref().join(JOURNAL_FILE);
+    if !journal_path.exists() {
+        return Ok(());
+    }
+    let journal = std::fs::read(&journal_path)?;
+    let mut history = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(folder.as_ref().join(HISTORY_FILE))?;
+    history.write_all(&journal)?;
+    history.sync_data()?;
+    std::fs::remove_file(&journal_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ClockState;
+    use crate::settings::Settings;
+    use crate::test_util::{admin, assert_same, data_folder};
+
+    fn folder(compact_after: usize) -> (tempfile::TempDir, Settings) {
+        let (dir, settings) = data_folder();
+        (
+            dir,
+            Settings {
+                compact_after,
+                ..settings
+            },
+        )
+    }
+
+    /// Adds two users, a finished record with an hours override and renames a user: 7 events.
+    fn changes(data: &mut HoursData) {
+        let anna = data.add_user(&admin(), "Anna").unwrap();
+        let bob = data.add_user(&admin(), "Bob").unwrap();
+        data.start(anna).unwrap();
+        data.end(anna).unwrap();
+        let index = data.dataframe.data[0].index;
+        data.set_hours(&admin(), index, "7:30").unwrap();
+        data.start(bob).unwrap();
+        data.rename_user(&admin(), bob, "Robert").unwrap();
+    }
+
+    fn lines(path: &Path) -> usize {
+        std::fs::read_to_string(path)
+            .map(|text| text.lines().count())
+            .unwrap_or(0)
+    }
+
+    #[test]
+    fn reload_replays_journal() {
+        let (dir, settings) = folder(100);
+        let mut data = HoursData::load(&settings).unwrap();
+        changes(&mut data);
+        assert_eq!(lines(&dir.path().join(JOURNAL_FILE)), 7);
+        assert_eq!(lines(&dir.path().join(HISTORY_FILE)), 0);
+        let snapshot = HoursData::from_store(dir.path()).unwrap();
+        assert!(snapshot.users.is_empty());
+
+        let loaded = HoursData::load(&settings).unwrap();
+        assert_same(&loaded, &data);
+        assert_eq!(loaded.dataframe.data[0].hours, "7.5");
+        assert_eq!(loaded.dataframe.data[1].name, "Robert");
+    }
+
+    #[test]
+    fn compaction_moves_journal_to_history() {
+        let (dir, settings) = folder(4);
+        let mut data = HoursData::load(&settings).unwrap();
+        changes(&mut data);
+        // compacted after the fourth event, three more since
+        assert_eq!(lines(&dir.path().join(HISTORY_FILE)), 4);
+        assert_eq!(lines(&dir.path().join(JOURNAL_FILE)), 3);
+        let snapshot = HoursData::from_store(dir.path()).unwrap();
+        assert_eq!(snapshot.users.len(), 2);
+        assert_eq!(snapshot.dataframe.data.len(), 1);
+        assert!(!snapshot.dataframe.data[0].end.is_empty());
+        assert_eq!(snapshot.dataframe.data[0].hours, "");
+
+        assert_same(&HoursData::load(&settings).unwrap(), &data);
+        let history = read(dir.path().join(HISTORY_FILE)).unwrap();
+        assert!(matches!(history[0].event, Event::UserAdded { .. }));
+    }
+
+    #[test]
+    fn incomplete_last_line_is_ignored() {
+        let (dir, settings) = folder(100);
+        let mut data = HoursData::load(&settings).unwrap();
+        changes(&mut data);
+        let journal_path = dir.path().join(JOURNAL_FILE);
+        // write interrupted in the middle of an event
+        let mut file = OpenOptions::new().append(true).open(&journal_path).unwrap();
+        write!(file, "{{\"time\":\"2023-04-03 08:00:00\",\"event\":\"Sta").unwrap();
+        drop(file);
+        assert_eq!(read(&journal_path).unwrap().len(), 7);
+
+        let mut loaded = HoursData::load(&settings).unwrap();
+        assert_same(&loaded, &data);
+        loaded.end(2).unwrap();
+        assert_eq!(lines(&journal_path), 8);
+        assert_same(&HoursData::load(&settings).unwrap(), &loaded);
+
+        // a complete event without newline is kept
+        let text = std::fs::read_to_string(&journal_path).unwrap();
+        std::fs::write(&journal_path, text.trim_end()).unwrap();
+        let mut loaded = HoursData::load(&settings).unwrap();
+        loaded.start(2).unwrap();
+        assert_eq!(read(&journal_path).unwrap().len(), 9);
+        assert_same(&HoursData::load(&settings).unwrap(), &loaded);
+
+        std::fs::write(&journal_path, format!("garbage\n{}", text)).unwrap();
+        assert!(read(&journal_path).is_err());
+    }
+
+    #[test]
+    fn replaying_saved_events_is_idempotent() {
+        let (dir, settings) = folder(100);
+        let mut data = HoursData::load(&settings).unwrap();
+        changes(&mut data);
+        // crash after the snapshot was written, before the journal was archived
+        data.save_to(dir.path()).unwrap();
+        assert_eq!(lines(&dir.path().join(JOURNAL_FILE)), 7);
+
+        let mut loaded = HoursData::load(&settings).unwrap();
+        assert_same(&loaded, &data);
+        assert_same(&HoursData::load(&settings).unwrap(), &data);
+        loaded.end(2).unwrap();
+        let reloaded = HoursData::load(&settings).unwrap();
+        assert_same(&reloaded, &loaded);
+        assert_eq!(reloaded.clock_state(2), ClockState::Idle);
+    }
+}
@@ -1,33 +1,79 @@
+mod auth;
+mod backup;
+mod chart;
+mod health;
+mod journal;
+mod lock;
+mod migration;
 mod model;
+mod recovery;
+mod report;
+mod settings;
+mod storage;
+#[cfg(test)]
+mod test_util;
 use crate::model::HoursData;
+use chrono::Datelike;
 use dioxus::{html::input_data::keyboard_types::Key, prelude::*};
 use dioxus_free_icons::icons::io_icons::{
     IoArrowDownCircleOutline, IoArrowUpCircleOutline, IoPencilOutline, IoPlayCircleOutline,
     IoStopCircleOutline, IoTrashBinOutline, IoArrowBackCircleOutline, IoArrowForwardCircleOutline,
+    IoArchiveOutline,
 };
 use dioxus_free_icons::Icon;
 use dioxus_router::{use_route, use_router, Route, Router};
-use model::{HoursRecord, Period};
+use model::{ClockState, Hours, HoursRecord, Orphan, Period, UserProfile};
+use settings::{Settings, SettingsStore};
+use storage::StorageKind;
+
+/// Converts the data folder to another storage backend (`json` or `sqlite`).
+/// `args` are the command line arguments, which may override the settings.
+pub fn convert_storage(args: &[String], to: &str) -> anyhow::Result<()> {
+    let settings = SettingsStore::load(args)?.effective();
+    storage::convert_folder(&settings, StorageKind::parse(to)?)
+}
+
+/// Attendance of a user on the home page.
+#[derive(PartialEq, Clone, Debug)]
+struct Presence {
+    pub id: u64,
+    /// See [HoursData::display_name].
+    pub name: String,
+    /// Text of the status badge.
+    pub status: String,
+    /// Css class of the status badge: `in`, `break`, `stale` or `off`.
+    pub class: &'static str,
+    /// Hours worked today, including a running record.
+    pub today: model::Hours,
+}
 
 #[derive(PartialEq, Props)]
-struct Names {
-    pub names: Vec<String>,
+struct Users {
+    pub users: Vec<Presence>,
 }
 
-fn show_names(cx: Scope<Names>) -> Element {
+fn show_names(cx: Scope<Users>) -> Element {
     let router = use_router(cx);
     cx.render(rsx! {
         ul{
-            for name in cx.props.names.iter(){
+            for user in cx.props.users.iter(){
                 p{
                     button{
                         class:"name",
                         onclick: move |_event|{
-                            let path = format!("/user/{}", name);
+                            let path = format!("/user/{}", user.id);
                             dbg!(&path);
                             router.navigate_to(&path);
                         },
-                        "{name}"
+                        "{user.name}"
+                    }
+                    span{
+                        class:"badge {user.class}",
+                        "{user.status}"
+                    }
+                    span{
+                        class:"today",
+                        "{user.today}"
                     }
                 }
             }
@@ -35,33 +81,164 @@ fn show_names(cx: Scope<Names>) -> Element {
     })
 }
 
-#[derive(Default, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Mode {
-    login_time: Option<chrono::DateTime<chrono::Local>>,
+    /// Time of the login or of the last activity since, see [Mode::touch].
+    last_activity: Option<chrono::DateTime<chrono::Local>>,
+    account: Option<auth::Access>,
+    /// Minutes without activity after which the login expires.
+    pub timeout_minutes: i64,
+    /// Folder with the admin accounts, see [auth::Accounts].
+    pub data_folder: std::path::PathBuf,
+    pub login_max_failures: u32,
+    pub login_lockout_minutes: i64,
+    /// Failed logins per account.
+    pub logins: auth::Throttle,
+    /// Failed PIN entries per user id.
+    pub pins: auth::Throttle,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::new(&Settings::default())
+    }
 }
 
 impl Mode {
-    pub fn is_admin(&self) -> bool {
-        if let Some(t) = self.login_time {
-            let now = chrono::Local::now();
-            let duration = now - t;
-            duration.num_seconds() < 60 * 10
+    pub fn new(settings: &Settings) -> Self {
+        let mut mode = Self {
+            last_activity: None,
+            account: None,
+            timeout_minutes: 0,
+            data_folder: std::path::PathBuf::new(),
+            login_max_failures: 0,
+            login_lockout_minutes: 0,
+            logins: auth::Throttle::default(),
+            pins: auth::Throttle::default(),
+        };
+        mode.apply_settings(settings);
+        mode
+    }
+
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        self.timeout_minutes = settings.admin_timeout_minutes;
+        self.data_folder = settings.data_folder.clone();
+        self.login_max_failures = settings.login_max_failures;
+        self.login_lockout_minutes = settings.login_lockout_minutes;
+    }
+
+    /// True until the first-run setup has created the admin account.
+    pub fn needs_setup(&self) -> bool {
+        auth::Accounts::load(&self.data_folder)
+            .map(|accounts| !accounts.is_set_up())
+            .unwrap_or_else(|e| {
+                println!("Error reading accounts: {}", e);
+                false
+            })
+    }
+
+    /// Time left until the login expires; None if nobody is logged in or the login has expired.
+    pub fn remaining(&self) -> Option<chrono::Duration> {
+        let t = self.last_activity?;
+        self.account.as_ref()?;
+        let remaining = t + chrono::Duration::minutes(self.timeout_minutes) - chrono::Local::now();
+        if remaining > chrono::Duration::zero() {
+            Some(remaining)
         } else {
-            false
+            None
         }
     }
 
-    pub fn login(&mut self, password: &str) -> bool {
-        if password == include_str!("../src/password.txt") {
-            self.login_time = Some(chrono::Local::now());
-            true
-        } else {
-            false
+    /// True if an account was logged in, but the login has expired since.
+    pub fn is_expired(&self) -> bool {
+        self.account.is_some() && self.remaining().is_none()
+    }
+
+    /// Extends a login that has not expired yet; called on every click and key press.
+    pub fn touch(&mut self) {
+        if self.remaining().is_some() {
+            self.last_activity = Some(chrono::Local::now());
+        }
+    }
+
+    /// The logged in account, unless the login has timed out.
+    pub fn access(&self) -> Option<auth::Access> {
+        self.remaining().and(self.account.clone())
+    }
+
+    pub fn require_access(&self) -> anyhow::Result<auth::Access> {
+        self.access()
+            .ok_or_else(|| anyhow::anyhow!("Please log in first"))
+    }
+
+    pub fn is_logged_in(&self) -> bool {
+        self.access().is_some()
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.access().map(|a| a.is_admin()).unwrap_or(false)
+    }
+
+    /// True if the logged in account may change the user and its records.
+    pub fn can_edit_user(&self, hours_data: &HoursData, user_id: u64) -> bool {
+        self.access()
+            .map(|a| hours_data.can_edit_user(&a, user_id))
+            .unwrap_or(false)
+    }
+
+    pub fn login(&mut self, name: &str, password: &str) -> anyhow::Result<()> {
+        let name = name.trim();
+        self.logins.check(name)?;
+        let accounts = auth::Accounts::load(&self.data_folder)?;
+        match accounts.account(name) {
+            Some(account) if accounts.verify(name, password) => {
+                self.logins.succeed(name);
+                self.account = Some(auth::Access::from(account));
+                self.last_activity = Some(chrono::Local::now());
+                Ok(())
+            }
+            _ => {
+                self.logins
+                    .fail(name, self.login_max_failures, self.login_lockout_minutes);
+                Err(anyhow::anyhow!("Wrong name or password"))
+            }
         }
     }
 
     pub fn logout(&mut self) {
-        self.login_time = None;
+        self.last_activity = None;
+        self.account = None;
+    }
+
+    /// Checks the PIN before the user starts or ends; accounts that may edit the user do not need the PIN.
+    /// Too many wrong PINs lock the user out for a while.
+    pub fn check_pin(
+        &mut self,
+        hours_data: &HoursData,
+        settings: &Settings,
+        user_id: u64,
+        pin: &str,
+    ) -> anyhow::Result<()> {
+        if self.can_edit_user(hours_data, user_id)
+            || !hours_data.needs_pin(user_id, settings.pin_required)
+        {
+            return Ok(());
+        }
+        if !hours_data.has_pin(user_id) {
+            return Err(anyhow::anyhow!(
+                "No PIN is set, please ask an administrator to set one"
+            ));
+        }
+        let key = user_id.to_string();
+        self.pins.check(&key)?;
+        if hours_data.verify_pin(user_id, pin) {
+            self.pins.succeed(&key);
+            Ok(())
+        } else {
+            self.pins
+                .fail(&key, settings.pin_max_failures, settings.pin_lockout_minutes);
+            Err(anyhow::anyhow!("Wrong PIN"))
+        }
     }
 }
 
@@ -74,9 +251,41 @@ struct TitleProps<'a> {
 fn page_title<'a>(cx: Scope<'a, TitleProps<'a>>) -> Element {
     let mode = cx.props.mode;
     let title_text = cx.props.title_text.clone();
+    let account_name = use_state(cx, || "".to_string());
     let password = use_state(cx, || "".to_string());
     let show_login = use_state(cx, || false);
+    let login_message = use_state(cx, || "".to_string());
     let router = use_router(cx);
+    let tick = use_state(cx, || 0u64);
+    // Redraws the countdown every second and logs out when the login expires.
+    use_future(cx, (), |_| {
+        let mode = mode.clone();
+        let router = router.clone();
+        let tick = tick.clone();
+        async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                if mode.read().is_expired() {
+                    mode.write().logout();
+                    router.navigate_to("/");
+                } else if mode.read().remaining().is_some() {
+                    tick.modify(|t| t + 1);
+                }
+            }
+        }
+    });
+    let access = mode.read().access();
+    let is_admin = access.as_ref().map(|a| a.is_admin()).unwrap_or(false);
+    let can_audit = access.as_ref().map(|a| a.can_audit()).unwrap_or(false);
+    let account_text = access
+        .as_ref()
+        .map(|a| format!("{} ({})", a.name, a.role))
+        .unwrap_or_default();
+    let remaining_text = mode
+        .read()
+        .remaining()
+        .map(|r| format!("{}:{:02}", r.num_minutes(), r.num_seconds() % 60))
+        .unwrap_or_default();
 
     cx.render(rsx! {
         h1 {
@@ -88,20 +297,104 @@ fn page_title<'a>(cx: Scope<'a, TitleProps<'a>>) -> Element {
                 },
                 "Home"
             }
-            if mode.read().is_admin(){
+            if access.is_some(){
                 rsx!{
+                    if is_admin{
+                        rsx!{
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    router.navigate_to("/settings");
+                                },
+                                "Settings"
+                            }
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    router.navigate_to("/backups");
+                                },
+                                "Backups"
+                            }
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    router.navigate_to("/accounts");
+                                },
+                                "Accounts"
+                            }
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    router.navigate_to("/health");
+                                },
+                                "Data health"
+                            }
+                        }
+                    }
+                    if can_audit{
+                        rsx!{
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    router.navigate_to("/history");
+                                },
+                                "History"
+                            }
+                        }
+                    }
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            router.navigate_to("/team");
+                        },
+                        "Team"
+                    }
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            router.navigate_to("/password");
+                        },
+                        "Password"
+                    }
                     button{
                         class:"menu",
                         onclick: move |_event|{
                             mode.write().logout();
+                            router.navigate_to("/");
                         },
                         "Logout"
                     }
+                    span{
+                        class:"account",
+                        "{account_text}"
+                    }
+                    span{
+                        class:"countdown",
+                        title: "Time until automatic logout",
+                        "{remaining_text}"
+                    }
                 }
             }
+            else if mode.read().needs_setup(){
+                rsx!(button{
+                    class:"admin",
+                    onclick: move |_event|{
+                        router.navigate_to("/setup");
+                    },
+                    "Set up admin"
+                })
+            }
             else{
                 if *show_login.get(){
                     rsx!(
+                        input{
+                        class: "account",
+                        placeholder: "Name",
+                        value: "{account_name}",
+                        oninput: move |event|{
+                            account_name.set(event.value.clone());
+                        },
+                    },
                         input{
                         class: "password",
                         "type": "password",
@@ -111,11 +404,20 @@ fn page_title<'a>(cx: Scope<'a, TitleProps<'a>>) -> Element {
                         },
                         onkeypress: move |event|{
                             if event.key()==Key::Enter{
-                                mode.write().login(&password.get());
-                                show_login.set(false);
+                                match mode.write().login(account_name.get(), password.get()){
+                                    Ok(()) => {
+                                        show_login.set(false);
+                                        login_message.set("".to_string());
+                                    }
+                                    Err(e) => login_message.set(e.to_string()),
+                                }
                                 password.set("".to_string());
                             }
                         },
+                    },
+                    span{
+                        class:"error",
+                        "{login_message}"
                     })
                 }
                 else{
@@ -125,7 +427,7 @@ fn page_title<'a>(cx: Scope<'a, TitleProps<'a>>) -> Element {
                             show_login.set(true);
                             password.set("".to_string());
                         },
-                        "Admin"
+                        "Login"
                     })
                 }
             },
@@ -134,140 +436,1734 @@ fn page_title<'a>(cx: Scope<'a, TitleProps<'a>>) -> Element {
 }
 
 #[derive(Props)]
-struct HoursDataProps<'a> {
-    pub hours_data: &'a UseRef<HoursData>,
+struct ModeProps<'a> {
     pub mode: &'a UseRef<Mode>,
 }
 
-fn edit_names<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+/// First-run setup of the admin password; only available while no admin account exists.
+fn setup_page<'a>(cx: Scope<'a, ModeProps<'a>>) -> Element {
+    let mode = cx.props.mode;
     let router = use_router(cx);
-    let hours_data = cx.props.hours_data;
-    let names = hours_data.read().names.clone();
-    let new_name = use_state(cx, || "".to_string());
+    let password = use_state(cx, || "".to_string());
+    let confirm = use_state(cx, || "".to_string());
+    let message = use_state(cx, || "".to_string());
+
     cx.render(rsx! {
-        ul{
-            for (i,name) in names.into_iter().enumerate(){
-                p {
-                    button{
-                        class:"name",
-                        onclick: move |_event|{
-                            let path = format!("/user/{}", name);
-                            dbg!(&path);
-                            router.navigate_to(&path);
+        page_title{
+            mode: mode,
+            title_text: "Set up admin".to_string(),
+        },
+        if mode.read().needs_setup(){
+            rsx!{
+                p{
+                    "Choose the admin password. It is stored hashed in the data folder."
+                },
+                div{
+                    span{
+                        class:"a",
+                        "Password"
+                    },
+                    input{
+                        class: "password",
+                        "type": "password",
+                        value: "{password}",
+                        oninput: move |event|{
+                            password.set(event.value.clone());
                         },
-                        "{name}"
                     },
                     span{
-                        onclick: move |_event|{
-                            let mut hours_data = hours_data.write();
-                            if hours_data.names.len()>1 && i>0{
-                                let x = hours_data.names.remove(i);
-                                hours_data.names.insert(i-1, x);
-                                hours_data.save();
+                        class:"e"
+                    }
+                },
+                div{
+                    span{
+                        class:"a",
+                        "Repeat password"
+                    },
+                    input{
+                        class: "password",
+                        "type": "password",
+                        value: "{confirm}",
+                        oninput: move |event|{
+                            confirm.set(event.value.clone());
+                        },
+                    },
+                    span{
+                        class:"e"
+                    }
+                },
+                button{
+                    class:"menu",
+                    onclick: move |_event|{
+                        if password.get() != confirm.get(){
+                            message.set("The passwords do not match".to_string());
+                            return;
+                        }
+                        let folder = mode.read().data_folder.clone();
+                        let result = auth::setup(&folder, password.get())
+                            .and_then(|_| mode.write().login(auth::ADMIN, password.get()));
+                        match result{
+                            Ok(()) => {
+                                password.set("".to_string());
+                                confirm.set("".to_string());
+                                router.navigate_to("/");
                             }
+                            Err(e) => message.set(e.to_string()),
+                        }
+                    },
+                    "Save"
+                },
+                p{
+                    class:"error",
+                    "{message}"
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "The admin account is already set up."
+                }
+            }
+        },
+    })
+}
+
+/// Lets the logged in account change its password.
+fn password_page<'a>(cx: Scope<'a, ModeProps<'a>>) -> Element {
+    let mode = cx.props.mode;
+    let current = use_state(cx, || "".to_string());
+    let password = use_state(cx, || "".to_string());
+    let confirm = use_state(cx, || "".to_string());
+    let message = use_state(cx, || "".to_string());
+    let fields = [
+        ("Current password", current),
+        ("New password", password),
+        ("Repeat new password", confirm),
+    ];
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "Change password".to_string(),
+        },
+        if mode.read().is_logged_in(){
+            rsx!{
+                for (label, value) in fields.into_iter(){
+                    div{
+                        span{
+                            class:"a",
+                            "{label}"
                         },
-                        class:"icon",
-                        Icon{
-                            width: 40,
-                            height: 40,
-                            //fill: "white",
-                            icon: IoArrowUpCircleOutline,
+                        input{
+                            class: "password",
+                            "type": "password",
+                            value: "{value}",
+                            oninput: move |event|{
+                                value.set(event.value.clone());
+                            },
+                        },
+                        span{
+                            class:"e"
+                        }
+                    }
+                },
+                button{
+                    class:"menu",
+                    onclick: move |_event|{
+                        if password.get() != confirm.get(){
+                            message.set("The passwords do not match".to_string());
+                            return;
+                        }
+                        let folder = mode.read().data_folder.clone();
+                        let result = mode.read().require_access().and_then(|access|{
+                            auth::change_password(&folder, &access.name, current.get(), password.get())
+                        });
+                        match result{
+                            Ok(()) => message.set("Password changed".to_string()),
+                            Err(e) => message.set(e.to_string()),
                         }
+                        current.set("".to_string());
+                        password.set("".to_string());
+                        confirm.set("".to_string());
                     },
-                    span{
+                    "Save"
+                },
+                p{
+                    "{message}"
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "Login to change the password."
+                }
+            }
+        },
+    })
+}
+
+/// Loads the accounts of the data folder, applies `change` and saves them if it succeeded.
+fn change_accounts<F>(mode: &UseRef<Mode>, change: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut auth::Accounts) -> anyhow::Result<()>,
+{
+    mode.read().require_access()?.require_admin()?;
+    let folder = mode.read().data_folder.clone();
+    let mut accounts = auth::Accounts::load(&folder)?;
+    change(&mut accounts)?;
+    accounts.save(&folder)
+}
+
+/// Admin page listing the accounts with their roles; accounts can be added, changed and removed.
+fn accounts_page<'a>(cx: Scope<'a, ModeProps<'a>>) -> Element {
+    let mode = cx.props.mode;
+    let message = use_state(cx, || "".to_string());
+    let new_name = use_state(cx, || "".to_string());
+    let new_role = use_state(cx, || auth::Role::Manager);
+    let new_team = use_state(cx, || "".to_string());
+    let new_password = use_state(cx, || "".to_string());
+    let folder = mode.read().data_folder.clone();
+    let accounts = auth::Accounts::load(&folder)
+        .map(|a| a.accounts)
+        .unwrap_or_else(|e| {
+            println!("Error loading accounts: {}", e);
+            Vec::new()
+        });
+    let edits = use_ref(cx, Vec::<(auth::Role, String)>::new);
+    if edits.read().len() != accounts.len() {
+        *edits.write_silent() = accounts.iter().map(|a| (a.role, a.team.clone())).collect();
+    }
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "Accounts".to_string(),
+        },
+        if mode.read().is_admin(){
+            rsx!{
+                for (i, account) in accounts.into_iter().enumerate(){
+                    div{
+                        span{
+                            class:"a",
+                            "{account.name}"
+                        },
+                        select{
+                            onchange: move |event|{
+                                if let Ok(role) = auth::Role::parse(&event.value){
+                                    edits.write_silent()[i].0 = role;
+                                }
+                            },
+                            for role in auth::Role::ALL{
+                                option{
+                                    value: "{role}",
+                                    selected: role == account.role,
+                                    "{role}"
+                                }
+                            }
+                        },
+                        input{
+                            placeholder: "Team",
+                            value: "{account.team}",
+                            oninput: move |event|{
+                                edits.write_silent()[i].1 = event.value.clone();
+                            },
+                        },
+                        button{
+                            class:"menu",
+                            onclick: {
+                                let name = account.name.clone();
+                                move |_event|{
+                                    let (role, team) = edits.read()[i].clone();
+                                    match change_accounts(mode, |a| a.set_role(&name, role, &team)){
+                                        Ok(()) => message.set(format!("Account {} changed", name)),
+                                        Err(e) => message.set(format!("Error changing {}: {}", name, e)),
+                                    }
+                                }
+                            },
+                            "Save"
+                        },
+                        button{
+                            class:"menu",
+                            onclick: move |_event|{
+                                match change_accounts(mode, |a| a.remove(&account.name)){
+                                    Ok(()) => {
+                                        edits.write().clear();
+                                        message.set(format!("Account {} removed", account.name));
+                                    }
+                                    Err(e) => message.set(format!("Error removing {}: {}", account.name, e)),
+                                }
+                            },
+                            "Remove"
+                        },
+                        span{
+                            class:"e"
+                        }
+                    }
+                },
+                div{
+                    input{
+                        placeholder: "Name",
+                        value: "{new_name}",
+                        oninput: move |event|{
+                            new_name.set(event.value.clone());
+                        },
+                    },
+                    select{
+                        onchange: move |event|{
+                            if let Ok(role) = auth::Role::parse(&event.value){
+                                new_role.set(role);
+                            }
+                        },
+                        for role in auth::Role::ALL{
+                            option{
+                                value: "{role}",
+                                selected: role == *new_role.get(),
+                                "{role}"
+                            }
+                        }
+                    },
+                    input{
+                        placeholder: "Team",
+                        value: "{new_team}",
+                        oninput: move |event|{
+                            new_team.set(event.value.clone());
+                        },
+                    },
+                    input{
+                        class: "password",
+                        "type": "password",
+                        placeholder: "Password",
+                        value: "{new_password}",
+                        oninput: move |event|{
+                            new_password.set(event.value.clone());
+                        },
+                    },
+                    button{
+                        class:"menu",
                         onclick: move |_event|{
-                            let mut hours_data = hours_data.write();
-                            if hours_data.names.len()>1 && i<hours_data.names.len()-1{
-                                let x = hours_data.names.remove(i);
-                                hours_data.names.insert(i+1, x);
-                                hours_data.save();
+                            let result = change_accounts(mode, |a|{
+                                a.add(new_name.get(), *new_role.get(), new_team.get(), new_password.get())
+                            });
+                            new_password.set("".to_string());
+                            match result{
+                                Ok(()) => {
+                                    edits.write().clear();
+                                    message.set(format!("Account {} added", new_name.get()));
+                                    new_name.set("".to_string());
+                                    new_team.set("".to_string());
+                                }
+                                Err(e) => message.set(format!("Error adding account: {}", e)),
                             }
                         },
-                        class:"icon",
-                        Icon{
-                            width: 40,
-                            height: 40,
-                            icon: IoArrowDownCircleOutline,
+                        "Add"
+                    }
+                },
+                p{
+                    "{message}"
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "Login as admin to manage the accounts."
+                }
+            }
+        },
+    })
+}
+
+#[derive(Props)]
+struct HoursDataProps<'a> {
+    pub hours_data: &'a UseRef<HoursData>,
+    pub mode: &'a UseRef<Mode>,
+    pub settings: &'a UseRef<SettingsStore>,
+}
+
+/// Users the logged in account may edit: all for admins, the own team for managers.
+fn edit_names<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let router = use_router(cx);
+    let hours_data = cx.props.hours_data;
+    let mode = cx.props.mode;
+    let access = mode.read().access();
+    let is_admin = mode.read().is_admin();
+    // status badge and today's hours by user id; archived users have none
+    let badges = presence(&hours_data.read());
+    let users = hours_data
+        .read()
+        .users
+        .iter()
+        .enumerate()
+        .filter(|(_i, u)| access.as_ref().map(|a| a.can_edit_user(u)).unwrap_or(false))
+        .map(|(i, u)| {
+            let data = hours_data.read();
+            let badge = badges.iter().find(|p| p.id == u.id);
+            (
+                i,
+                u.id,
+                data.display_name(u.id),
+                u.archived,
+                data.record_count(u.id) > 0,
+                badge.map(|p| p.status.clone()).unwrap_or_default(),
+                badge.map(|p| p.class).unwrap_or_default(),
+                badge.map(|p| p.today.to_string()).unwrap_or_default(),
+            )
+        })
+        .collect::<Vec<_>>();
+    let orphans = if is_admin {
+        hours_data.read().orphans().len()
+    } else {
+        0
+    };
+    let new_name = use_state(cx, || "".to_string());
+    let renaming = use_state(cx, || None::<u64>);
+    let rename_to = use_state(cx, || "".to_string());
+    let message = use_state(cx, || "".to_string());
+    cx.render(rsx! {
+        p{
+            class:"error",
+            "{message}"
+        },
+        ul{
+            for (i,id,name,archived,has_records,status,class,today) in users.into_iter(){
+                p {
+                    class: if archived {"archived"} else {""},
+                    if *renaming.get() == Some(id){
+                        rsx!{
+                            input{
+                                value: "{rename_to}",
+                                oninput: move |event|{
+                                    rename_to.set(event.value.clone());
+                                },
+                                onkeypress: move |event|{
+                                    if event.key()==Key::Enter{
+                                        let result = mode.read().require_access().and_then(|access|{
+                                            hours_data.write().rename_user(&access, id, rename_to.get())
+                                        });
+                                        match result{
+                                            Ok(()) => {
+                                                renaming.set(None);
+                                                message.set("".to_string());
+                                            }
+                                            Err(e) => message.set(format!("Error renaming user: {}", e)),
+                                        }
+                                    }
+                                    else if event.key()==Key::Escape{
+                                        renaming.set(None);
+                                    }
+                                },
+                            }
+                        }
+                    }
+                    else{
+                        rsx!{
+                            button{
+                                class:"name",
+                                onclick: move |_event|{
+                                    let path = format!("/user/{}", id);
+                                    dbg!(&path);
+                                    router.navigate_to(&path);
+                                },
+                                "{name}"
+                            },
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    let current = hours_data.read().user(id).map(|u| u.name.clone()).unwrap_or_default();
+                                    rename_to.set(current);
+                                    renaming.set(Some(id));
+                                },
+                                "Rename"
+                            }
+                        }
+                    },
+                    if !status.is_empty(){
+                        rsx!{
+                            span{
+                                class:"badge {class}",
+                                "{status}"
+                            }
+                            span{
+                                class:"today",
+                                "{today}"
+                            }
+                        }
+                    }
+                    span{
+                        onclick: move |_event|{
+                            router.navigate_to(&format!("/profile/{}", id));
+                        },
+                        class:"icon",
+                        Icon{
+                            width: 40,
+                            height: 40,
+                            icon: IoPencilOutline,
+                        }
+                    },
+                    if is_admin{
+                        rsx!{
+                            span{
+                                onclick: move |_event|{
+                                    let access = mode.read().require_access();
+                                    let mut hours_data = hours_data.write();
+                                    if hours_data.users.len()>1 && i>0{
+                                        match access.and_then(|access| hours_data.move_user(&access, i, i-1)){
+                                            Ok(()) => message.set("".to_string()),
+                                            Err(e) => message.set(format!("Error moving user: {}", e)),
+                                        }
+                                    }
+                                },
+                                class:"icon",
+                                Icon{
+                                    width: 40,
+                                    height: 40,
+                                    //fill: "white",
+                                    icon: IoArrowUpCircleOutline,
+                                }
+                            },
+                            span{
+                                onclick: move |_event|{
+                                    let access = mode.read().require_access();
+                                    let mut hours_data = hours_data.write();
+                                    if hours_data.users.len()>1 && i<hours_data.users.len()-1{
+                                        match access.and_then(|access| hours_data.move_user(&access, i, i+1)){
+                                            Ok(()) => message.set("".to_string()),
+                                            Err(e) => message.set(format!("Error moving user: {}", e)),
+                                        }
+                                    }
+                                },
+                                class:"icon",
+                                Icon{
+                                    width: 40,
+                                    height: 40,
+                                    icon: IoArrowDownCircleOutline,
+                                }
+                            },
+                        }
+                    }
+                    if archived{
+                        rsx!{
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    let result = mode.read().require_access().and_then(|access|{
+                                        hours_data.write().set_archived(&access, id, false)
+                                    });
+                                    if let Err(e) = result{
+                                        message.set(format!("Error restoring user: {}", e));
+                                    }
+                                },
+                                "Restore"
+                            }
+                            if !has_records{
+                                rsx!{
+                                    span{
+                                        onclick: move |_event|{
+                                            let result = mode.read().require_access().and_then(|access|{
+                                                hours_data.write().remove_user(&access, i)
+                                            });
+                                            if let Err(e) = result{
+                                                message.set(format!("Error removing user: {}", e));
+                                            }
+                                        },
+                                        class:"icon",
+                                        Icon{
+                                            width: 40,
+                                            height: 40,
+                                            icon: IoTrashBinOutline,
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    else{
+                        rsx!{
+                            span{
+                                onclick: move |_event|{
+                                    let result = mode.read().require_access().and_then(|access|{
+                                        hours_data.write().set_archived(&access, id, true)
+                                    });
+                                    if let Err(e) = result{
+                                        message.set(format!("Error archiving user: {}", e));
+                                    }
+                                },
+                                class:"icon",
+                                Icon{
+                                    width: 40,
+                                    height: 40,
+                                    icon: IoArchiveOutline,
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        },
+        if orphans > 0{
+            rsx!{
+                p{
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            router.navigate_to("/orphans");
+                        },
+                        "Records of unknown users ({orphans})"
+                    }
+                }
+            }
+        }
+        input{
+            value: "{new_name}",
+            oninput: move |event|{
+                let _hours_data = hours_data.write();
+                new_name.set(event.value.clone());
+            },
+            onkeypress: move |event|{
+                if event.key()==Key::Enter{
+                    let result = mode.read().require_access().and_then(|access|{
+                        hours_data.write().add_user(&access, new_name.get())
+                    });
+                    if let Err(e) = result{
+                        message.set(format!("Error adding user: {}", e));
+                    }
+                    new_name.set("".to_string());
+                }
+            },
+
+        },
+    })
+}
+
+/// Attendance of the users that are not archived. A user who is not working,
+/// but has worked today, is on break since the end of the last record.
+fn presence(hours_data: &HoursData) -> Vec<Presence> {
+    let today = chrono::Local::now().date_naive();
+    let dataframe = &hours_data.dataframe;
+    hours_data
+        .users
+        .iter()
+        .filter(|u| !u.archived)
+        .map(|u| {
+            let worked = dataframe.hours_for_day(u.id, today);
+            let record = |index| dataframe.record(index).cloned().unwrap_or_default();
+            let (status, class) = match hours_data.clock_state(u.id) {
+                ClockState::Working { index } => {
+                    (format!("In since {}", record(index).start_time()), "in")
+                }
+                ClockState::Stale { index } => {
+                    let record = record(index);
+                    (format!("Not ended since {}", record.date()), "stale")
+                }
+                ClockState::Idle => {
+                    let last_end = dataframe
+                        .data
+                        .iter()
+                        .filter(|r| r.user_id == u.id)
+                        .filter(|r| r.end_dt().map(|e| e.date() == today).unwrap_or(false))
+                        .map(|r| r.end_time())
+                        .max();
+                    match last_end {
+                        Some(end) => (format!("On break since {}", end), "break"),
+                        None => ("Off".to_string(), "off"),
+                    }
+                }
+            };
+            Presence {
+                id: u.id,
+                name: hours_data.display_name(u.id),
+                status,
+                class,
+                today: worked,
+            }
+        })
+        .collect()
+}
+
+/// Shown instead of the usual content when the data failed to load and the app runs read-only.
+fn load_error_view<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let settings = cx.props.settings;
+    let mode = cx.props.mode;
+    let router = use_router(cx);
+    let message = use_state(cx, || "".to_string());
+    let load_error = hours_data.read().load_error.clone().unwrap_or_default();
+
+    cx.render(rsx! {
+        div{
+            class:"error",
+            p{
+                "The hours data could not be loaded, the app is read-only and will not save anything."
+            },
+            p{
+                "{load_error}"
+            },
+            p{
+                "{message}"
+            },
+            if mode.read().is_admin(){
+                rsx!{
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            match HoursData::load(&settings.read().effective()){
+                                Ok(data) => {
+                                    hours_data.set(data);
+                                    message.set("".to_string());
+                                }
+                                Err(e) => {
+                                    message.set(format!("Still failing: {}", e));
+                                }
+                            }
+                        },
+                        "Reload"
+                    }
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            let settings = settings.read().effective();
+                            match recovery::repair(&settings){
+                                Ok(salvage) => {
+                                    hours_data.set(salvage.data);
+                                    message.set(format!("Repaired, {} unreadable records dropped", salvage.dropped));
+                                }
+                                Err(e) => {
+                                    message.set(format!("Repair failed: {}", e));
+                                }
+                            }
+                        },
+                        "Repair"
+                    }
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            router.navigate_to("/backups");
+                        },
+                        "Restore backup"
+                    }
+                }
+            }
+            else{
+                rsx!{
+                    p{
+                        "Please contact an administrator."
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Asks whether to reload or merge after a save was refused because another program changed the data.
+fn conflict_view<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let message = use_state(cx, || "".to_string());
+    let conflict = match hours_data.read().conflict.clone() {
+        Some(conflict) => conflict,
+        None => return None,
+    };
+    let unsaved = hours_data.read().unsaved.len();
+
+    cx.render(rsx! {
+        div{
+            class:"error",
+            p{
+                "{conflict}. {unsaved} change(s) were not saved: Merge applies them to the changed data, Reload drops them."
+            },
+            p{
+                "{message}"
+            },
+            button{
+                class:"menu",
+                onclick: move |_event|{
+                    if let Err(e) = hours_data.write().merge_external(){
+                        message.set(format!("Merge failed: {}", e));
+                    }
+                },
+                "Merge"
+            }
+            button{
+                class:"menu",
+                onclick: move |_event|{
+                    if let Err(e) = hours_data.write().reload(){
+                        message.set(format!("Reload failed: {}", e));
+                    }
+                },
+                "Reload"
+            }
+        }
+    })
+}
+
+fn users_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let settings = cx.props.settings;
+    let mode = cx.props.mode;
+    let users = presence(&hours_data.read());
+    let present = users.iter().filter(|u| u.class == "in").count();
+    let total = users.len();
+    let read_only = hours_data.read().is_read_only();
+    let tick = use_state(cx, || 0u64);
+    // Redraws the badges and today's hours while anybody is working.
+    use_future(cx, (), |_| {
+        let hours_data = hours_data.clone();
+        let tick = tick.clone();
+        async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                let working = {
+                    let data = hours_data.read();
+                    data.users
+                        .iter()
+                        .any(|u| matches!(data.clock_state(u.id), ClockState::Working { .. }))
+                };
+                if working {
+                    tick.modify(|t| t + 1);
+                }
+            }
+        }
+    });
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "Users".to_string(),
+        },
+        conflict_view{
+            hours_data: hours_data,
+            mode: mode,
+            settings: settings,
+        },
+        p{
+            class:"present",
+            "{present} of {total} present"
+        },
+        if read_only{
+            rsx!{
+                load_error_view{
+                    hours_data: hours_data,
+                    mode: mode,
+                    settings: settings,
+                }
+            }
+        }
+        else if mode.read().access().map(|a| a.can_edit()).unwrap_or(false){
+            rsx!{
+                edit_names{
+                    hours_data: hours_data,
+                    mode: mode,
+                    settings: settings,
+                }
+            }
+        }
+        else{
+            rsx!{
+                show_names{
+                    users: users,
+                }
+            }
+        },
+    })
+}
+
+/// Admin page listing the problems found by [health::check], each with its fix if there is one.
+fn health_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let mode = cx.props.mode;
+    let router = use_router(cx);
+    let message = use_state(cx, || "".to_string());
+    let issues = health::check(&hours_data.read().dataframe)
+        .into_iter()
+        .map(|issue| {
+            let name = hours_data.read().display_name(issue.user_id);
+            let label = issue.fix_label().unwrap_or_default();
+            (issue.user_id, name, issue.describe(), label, issue)
+        })
+        .collect::<Vec<_>>();
+    let fixable = issues.iter().filter(|(_, _, _, label, _)| !label.is_empty()).count();
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "Data health".to_string(),
+        },
+        if mode.read().is_admin(){
+            rsx!{
+                p{
+                    "{message}"
+                },
+                if issues.is_empty(){
+                    rsx!{
+                        p{
+                            "No problems found."
+                        }
+                    }
+                }
+                else if fixable > 0{
+                    rsx!{
+                        button{
+                            class:"menu",
+                            onclick: move |_event|{
+                                let result = mode.read().require_access().and_then(|access|{
+                                    hours_data.write().fix_all(&access)
+                                });
+                                match result{
+                                    Ok(n) => message.set(format!("Fixed {} problems", n)),
+                                    Err(e) => message.set(format!("Error fixing: {}", e)),
+                                }
+                            },
+                            "Fix all ({fixable})"
+                        }
+                    }
+                }
+                for (user_id, name, description, label, issue) in issues.into_iter(){
+                    div{
+                        key: "{issue.index}-{description}",
+                        span{
+                            class:"a",
+                            onclick: move |_event|{
+                                router.navigate_to(&format!("/user/{}", user_id));
+                            },
+                            "{name}"
+                        },
+                        span{
+                            class:"b",
+                            "{issue.date}"
+                        },
+                        span{
+                            class:"b",
+                            "Record {issue.index}: {description}"
+                        },
+                        if !label.is_empty(){
+                            rsx!{
+                                button{
+                                    class:"menu",
+                                    onclick: move |_event|{
+                                        let result = mode.read().require_access().and_then(|access|{
+                                            hours_data.write().fix_issue(&access, &issue)
+                                        });
+                                        match result{
+                                            Ok(()) => message.set(format!("Fixed record {}", issue.index)),
+                                            Err(e) => message.set(format!("Error fixing record {}: {}", issue.index, e)),
+                                        }
+                                    },
+                                    "{label}"
+                                }
+                            }
+                        }
+                        span{
+                            class:"e"
+                        }
+                    }
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "Login as admin to check the data."
+                }
+            }
+        },
+    })
+}
+
+/// Admin page listing records whose user is not in the user list,
+/// with the options to create a user for them or to merge them into an existing user.
+fn orphans_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let mode = cx.props.mode;
+    let message = use_state(cx, || "".to_string());
+    let targets = use_ref(cx, Vec::<u64>::new);
+    let orphans: Vec<Orphan> = hours_data.read().orphans();
+    let users: Vec<(u64, String)> = hours_data
+        .read()
+        .users
+        .iter()
+        .map(|u| (u.id, hours_data.read().display_name(u.id)))
+        .collect();
+    if targets.read().len() != orphans.len() {
+        let first = users.first().map(|(id, _)| *id).unwrap_or(0);
+        targets.write_silent().resize(orphans.len(), first);
+    }
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "Records of unknown users".to_string(),
+        },
+        if mode.read().is_admin(){
+            rsx!{
+                p{
+                    "{message}"
+                },
+                if orphans.is_empty(){
+                    rsx!{
+                        p{
+                            "All records belong to a user."
+                        }
+                    }
+                }
+                for (i, orphan) in orphans.into_iter().enumerate(){
+                    div{
+                        span{
+                            class:"a",
+                            "{orphan.name}"
+                        },
+                        span{
+                            class:"b",
+                            "{orphan.records} records, {orphan.first_date} - {orphan.last_date}"
+                        },
+                        button{
+                            class:"menu",
+                            onclick: move |_event|{
+                                let result = mode.read().require_access().and_then(|access|{
+                                    hours_data.write().restore_orphan(&access, &orphan)
+                                });
+                                match result{
+                                    Ok(_) => message.set(format!("Created user {}", orphan.name)),
+                                    Err(e) => message.set(format!("Error creating user: {}", e)),
+                                }
+                            },
+                            "Create user"
+                        },
+                        select{
+                            onchange: move |event|{
+                                if let Ok(id) = event.value.parse(){
+                                    targets.write_silent()[i] = id;
+                                }
+                            },
+                            for (id, name) in users.iter(){
+                                option{
+                                    value: "{id}",
+                                    "{name}"
+                                }
+                            }
+                        },
+                        button{
+                            class:"menu",
+                            onclick: move |_event|{
+                                let target = targets.read()[i];
+                                let orphan = match hours_data.read().orphans().get(i).cloned(){
+                                    Some(orphan) => orphan,
+                                    None => return,
+                                };
+                                let result = mode.read().require_access().and_then(|access|{
+                                    hours_data.write().attach_orphan(&access, &orphan, target)
+                                });
+                                match result{
+                                    Ok(()) => message.set(format!("Merged {}", orphan.name)),
+                                    Err(e) => message.set(format!("Error merging: {}", e)),
+                                }
+                            },
+                            "Merge"
+                        },
+                        span{
+                            class:"e"
+                        }
+                    }
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "Login as admin to see records of unknown users."
+                }
+            }
+        },
+    })
+}
+
+/// Admin page editing the profile of the user in the route.
+fn profile_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let route = use_route(cx);
+    let user_id = route_user_id(route);
+    let hours_data = cx.props.hours_data;
+    let mode = cx.props.mode;
+    let profile = use_ref(cx, || hours_data.read().user(user_id).cloned());
+    let weekly_hours = use_state(cx, || {
+        profile
+            .read()
+            .as_ref()
+            .map(|p| p.weekly_hours.to_string())
+            .unwrap_or_default()
+    });
+    let message = use_state(cx, || "".to_string());
+    let new_pin = use_state(cx, || "".to_string());
+    let title = hours_data.read().display_name(user_id);
+    let pin_status = if hours_data.read().has_pin(user_id) {
+        "PIN is set"
+    } else {
+        "No PIN"
+    };
+    let user = match profile.read().clone() {
+        Some(user) => user,
+        None => {
+            return cx.render(rsx! {
+                page_title{
+                    mode: mode,
+                    title_text: title,
+                },
+                p{
+                    "This user does not exist."
+                }
+            })
+        }
+    };
+    let fields: Vec<(&str, String, fn(&mut UserProfile, String))> = vec![
+        ("Name", user.name.clone(), |p, v| p.name = v),
+        ("Employee number", user.employee_number.clone(), |p, v| p.employee_number = v),
+        ("Email", user.email.clone(), |p, v| p.email = v),
+        ("Team", user.team.clone(), |p, v| p.team = v),
+        ("Contract", user.contract.clone(), |p, v| p.contract = v),
+    ];
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: title,
+        },
+        if mode.read().can_edit_user(&hours_data.read(), user_id){
+            rsx!{
+                for (label, value, set) in fields.into_iter(){
+                    div{
+                        span{
+                            class:"a",
+                            "{label}"
+                        },
+                        input{
+                            value: "{value}",
+                            oninput: move |event|{
+                                if let Some(p) = profile.write().as_mut(){
+                                    set(p, event.value.clone());
+                                }
+                            },
+                        },
+                        span{
+                            class:"e"
+                        }
+                    }
+                },
+                div{
+                    span{
+                        class:"a",
+                        "Weekly hours"
+                    },
+                    input{
+                        value: "{weekly_hours}",
+                        oninput: move |event|{
+                            weekly_hours.set(event.value.clone());
+                        },
+                    },
+                    span{
+                        class:"e"
+                    }
+                },
+                div{
+                    span{
+                        class:"a",
+                        "{pin_status}"
+                    },
+                    input{
+                        class: "password",
+                        "type": "password",
+                        value: "{new_pin}",
+                        oninput: move |event|{
+                            new_pin.set(event.value.clone());
+                        },
+                    },
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            let result = mode.read().require_access().and_then(|access|{
+                                hours_data.write().set_pin(&access, user_id, new_pin.get())
+                            });
+                            new_pin.set("".to_string());
+                            match result{
+                                Ok(()) => message.set("PIN set".to_string()),
+                                Err(e) => message.set(format!("Error setting PIN: {}", e)),
+                            }
+                            let pin_hash = hours_data.read().user(user_id).map(|u| u.pin_hash.clone());
+                            if let (Some(p), Some(pin_hash)) = (profile.write().as_mut(), pin_hash){
+                                p.pin_hash = pin_hash;
+                            }
+                        },
+                        "Set PIN"
+                    },
+                    button{
+                        class:"menu",
+                        onclick: move |_event|{
+                            let result = mode.read().require_access().and_then(|access|{
+                                hours_data.write().reset_pin(&access, user_id)
+                            });
+                            match result{
+                                Ok(()) => message.set("PIN reset".to_string()),
+                                Err(e) => message.set(format!("Error resetting PIN: {}", e)),
+                            }
+                            if let Some(p) = profile.write().as_mut(){
+                                p.pin_hash = hours_data.read().user(user_id).map(|u| u.pin_hash.clone()).unwrap_or_default();
+                            }
+                        },
+                        "Reset PIN"
+                    },
+                    span{
+                        class:"e"
+                    }
+                },
+                button{
+                    class:"menu",
+                    onclick: move |_event|{
+                        let mut user = match profile.read().clone(){
+                            Some(user) => user,
+                            None => return,
+                        };
+                        match weekly_hours.get().trim().replace(',', ".").parse::<f64>(){
+                            Ok(hours) if hours >= 0.0 => user.weekly_hours = hours,
+                            _ => {
+                                message.set(format!("Invalid weekly hours {}", weekly_hours.get()));
+                                return;
+                            }
+                        }
+                        let result = mode.read().require_access().and_then(|access|{
+                            hours_data.write().save_profile(&access, user)
+                        });
+                        match result{
+                            Ok(()) => message.set("Profile saved".to_string()),
+                            Err(e) => message.set(format!("Error saving profile: {}", e)),
+                        }
+                    },
+                    "Save"
+                },
+                p{
+                    "{message}"
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "Login with an account that may edit this profile."
+                }
+            }
+        },
+    })
+}
+
+/// Audit history: the recorded changes, newest first. Available to admins and auditors.
+fn history_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let mode = cx.props.mode;
+    let can_audit = mode.read().access().map(|a| a.can_audit()).unwrap_or(false);
+    let entries = if can_audit {
+        let storage = hours_data.read().storage.clone();
+        storage
+            .map(|s| s.history())
+            .unwrap_or_else(|| Ok(Vec::new()))
+            .unwrap_or_else(|e| {
+                println!("Error reading history: {}", e);
+                Vec::new()
+            })
+    } else {
+        Vec::new()
+    };
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "History".to_string(),
+        },
+        if can_audit{
+            rsx!{
+                for entry in entries.into_iter().rev(){
+                    div{
+                        span{
+                            class:"a",
+                            "{entry.time}"
+                        },
+                        span{
+                            class:"b",
+                            entry.event.describe()
+                        },
+                        span{
+                            class:"e"
+                        }
+                    }
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "Login as admin or auditor to see the history."
+                }
+            }
+        },
+    })
+}
+
+fn backups_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let settings = cx.props.settings;
+    let mode = cx.props.mode;
+    let message = use_state(cx, || "".to_string());
+    let folder = hours_data.read().folder.clone();
+    let backups = backup::list(&folder).unwrap_or_else(|e| {
+        println!("Error listing backups: {}", e);
+        Vec::new()
+    });
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "Backups".to_string(),
+        },
+        if mode.read().is_admin(){
+            rsx!{
+                p{
+                    "{message}"
+                },
+                ul{
+                    for entry in backups.into_iter(){
+                        p{
+                            span{
+                                class:"a",
+                                "{entry.time_text()}"
+                            },
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    let folder = hours_data.read().folder.clone();
+                                    let settings = settings.read().effective();
+                                    match backup::restore(&folder, &entry.name).and_then(|_| HoursData::load(&settings)){
+                                        Ok(data) => {
+                                            hours_data.set(data);
+                                            message.set(format!("Restored backup from {}", entry.time_text()));
+                                        }
+                                        Err(e) => {
+                                            message.set(format!("Error restoring backup: {}", e));
+                                        }
+                                    }
+                                },
+                                "Restore"
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        else{
+            rsx!{
+                p{
+                    "Login as admin to restore backups."
+                }
+            }
+        },
+    })
+}
+
+#[derive(Props)]
+struct SettingsProps<'a> {
+    pub hours_data: &'a UseRef<HoursData>,
+    pub mode: &'a UseRef<Mode>,
+    pub settings: &'a UseRef<SettingsStore>,
+}
+
+fn settings_page<'a>(cx: Scope<'a, SettingsProps<'a>>) -> Element {
+    let hours_data = cx.props.hours_data;
+    let mode = cx.props.mode;
+    let settings = cx.props.settings;
+    let fields = use_ref(cx, || settings.read().file.fields().unwrap_or_default());
+    let message = use_state(cx, || "".to_string());
+    let rows = fields.read().clone();
+    let path = settings.read().path.display().to_string();
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: "Settings".to_string(),
+        },
+        if mode.read().is_admin(){
+            rsx!{
+                p{
+                    "Settings file: {path}"
+                },
+                for (i, (name, value)) in rows.into_iter().enumerate(){
+                    div{
+                        span{
+                            class:"a",
+                            "{name}"
+                        },
+                        input{
+                            value: "{value}",
+                            oninput: move |event|{
+                                fields.write()[i].1 = event.value.clone();
+                            },
+                        },
+                        if settings.read().is_overridden(&name){
+                            rsx!{
+                                span{
+                                    " overridden by the command line or environment"
+                                }
+                            }
                         }
-                    },
-                    span{
-                        onclick: move |_event|{
-                            let mut hours_data = hours_data.write();
-                            if !hours_data.names.is_empty(){
-                                hours_data.names.remove(i);
-                                hours_data.save();
+                        else{
+                            rsx!{
+                                span{}
                             }
                         },
-                        class:"icon",
-                        Icon{
-                            width: 40,
-                            height: 40,
-                            icon: IoTrashBinOutline,
+                        span{
+                            class:"e"
                         }
                     }
+                },
+                button{
+                    class:"menu",
+                    onclick: move |_event|{
+                        let edits = fields.read().clone();
+                        let file = settings.read().file.with_overrides(&edits);
+                        match file{
+                            Ok(file) => {
+                                settings.write().file = file;
+                                if let Err(e) = settings.read().save(){
+                                    message.set(format!("Error saving settings: {}", e));
+                                    return;
+                                }
+                                let effective = settings.read().effective();
+                                mode.write().apply_settings(&effective);
+                                hours_data.set(HoursData::load_or_safe_mode(&effective));
+                                message.set("Settings saved".to_string());
+                            }
+                            Err(e) => {
+                                message.set(format!("{}", e));
+                            }
+                        }
+                    },
+                    "Save"
+                },
+                p{
+                    "{message}"
                 }
             }
-        },
-        input{
-            value: "{new_name}",
-            oninput: move |event|{
-                let _hours_data = hours_data.write();
-                new_name.set(event.value.clone());
-            },
-            onkeypress: move |event|{
-                if event.key()==Key::Enter{
-                    let mut hours_data = hours_data.write();
-                    hours_data.names.push(new_name.get().clone());
-                    new_name.set("".to_string());
-                    hours_data.save();
+        }
+        else{
+            rsx!{
+                p{
+                    "Login as admin to change settings."
                 }
-            },
-
+            }
         },
     })
 }
 
-fn users_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+/// Id of the user in the `:id` segment of the route; 0 (no user) if missing.
+fn route_user_id(route: &dioxus_router::UseRoute) -> u64 {
+    route
+        .segment("id")
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(0)
+}
+
+/// Hours of the users the account may see for each day of a month, with totals per user and day.
+/// Clicking a user opens the month in the user view.
+fn team_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
     let hours_data = cx.props.hours_data;
     let mode = cx.props.mode;
-    let names = hours_data.read().names.clone();
+    let router = use_router(cx);
+    let period = use_state(cx, Period::current);
+    let access = mode.read().access();
+    let data = hours_data.read();
+    let rows = data
+        .users
+        .iter()
+        .filter(|u| {
+            access
+                .as_ref()
+                .map(|a| a.can_audit() || a.can_edit_user(u))
+                .unwrap_or(false)
+        })
+        .map(|u| {
+            let days = data.dataframe.hours_by_day(u.id, period.get());
+            let total: model::Hours = days.iter().copied().sum();
+            (u.id, data.display_name(u.id), days, total)
+        })
+        .collect::<Vec<_>>();
+    drop(data);
+    let days = period.get().days() as usize;
+    let day_totals = (0..days)
+        .map(|d| rows.iter().map(|(_, _, hours, _)| hours[d]).sum::<model::Hours>())
+        .collect::<Vec<_>>();
+    let total: model::Hours = rows.iter().map(|(_, _, _, total)| *total).sum();
+    let (year, month) = (period.get().year, period.get().month);
+    let day_labels = (1..=days).map(|d| d.to_string()).collect::<Vec<_>>();
+    let series = rows
+        .iter()
+        .map(|(_, name, hours, _)| (name.clone(), hours.clone()))
+        .collect::<Vec<_>>();
+    let team_chart = chart::stacked_bar_chart(&day_labels, &series);
+
     cx.render(rsx! {
         page_title{
             mode: mode,
-            title_text: "Users".to_string(),
+            title_text: "Team".to_string(),
         },
-        if mode.read().is_admin(){
+        if access.is_some(){
             rsx!{
-                edit_names{
-                    hours_data: hours_data,
-                    mode: mode,
+                span{
+                    class:"period",
+                    "{period}"
+                },
+                span{
+                    class:"perdiodbutton",
+                    onclick: move |_event|{
+                        period.set(period.get().previous());
+                    },
+                    Icon{
+                        width: 24,
+                        height: 24,
+                        icon: IoArrowBackCircleOutline,
+                    }
+                },
+                span{
+                    class:"perdiodbutton",
+                    onclick: move |_event|{
+                        period.set(period.get().next());
+                    },
+                    Icon{
+                        width: 24,
+                        height: 24,
+                        icon: IoArrowForwardCircleOutline,
+                    }
+                },
+                table{
+                    class:"grid",
+                    tr{
+                        th{},
+                        for day in 1..=days{
+                            th{
+                                "{day}"
+                            }
+                        }
+                        th{
+                            "Total"
+                        }
+                    }
+                    for (id, name, hours, user_total) in rows.into_iter(){
+                        tr{
+                            key: "{id}",
+                            td{
+                                class:"link",
+                                onclick: move |_event|{
+                                    router.navigate_to(&format!("/user/{}/{}/{}", id, year, month));
+                                },
+                                "{name}"
+                            }
+                            for h in hours.into_iter(){
+                                td{
+                                    if h.seconds() != 0{
+                                        rsx!{"{h}"}
+                                    }
+                                }
+                            }
+                            td{
+                                b{"{user_total}"}
+                            }
+                        }
+                    }
+                    tr{
+                        td{
+                            b{"Total"}
+                        }
+                        for h in day_totals.into_iter(){
+                            td{
+                                if h.seconds() != 0{
+                                    rsx!{b{"{h}"}}
+                                }
+                            }
+                        }
+                        td{
+                            b{"{total}"}
+                        }
+                    }
+                }
+                div{
+                    class:"chart",
+                    dangerous_inner_html: "{team_chart}",
                 }
             }
         }
         else{
             rsx!{
-                show_names{
-                    names: names,
+                p{
+                    "Login to see the team overview."
                 }
             }
         },
     })
 }
 
+/// Worked hours of a user per month of a year, with totals and a csv export.
+fn year_page<'a>(cx: Scope<'a, HoursDataProps<'a>>) -> Element {
+    let route = use_route(cx);
+    let user_id = route_user_id(route);
+    let hours_data = cx.props.hours_data;
+    let mode = cx.props.mode;
+    let year = use_state(cx, || Period::current().year);
+    let message = use_state(cx, || "".to_string());
+    let name = hours_data.read().display_name(user_id);
+    let summary = report::year_summary(&hours_data.read().dataframe, user_id, *year.get());
+    let months = summary.months.clone();
+    let total = summary.total.clone();
+
+    cx.render(rsx! {
+        page_title{
+            mode: mode,
+            title_text: format!("{} {}", name, year.get()),
+        },
+        span{
+            class:"perdiodbutton",
+            onclick: move |_event|{
+                year.set(year.get() - 1);
+            },
+            Icon{
+                width: 24,
+                height: 24,
+                icon: IoArrowBackCircleOutline,
+            }
+        },
+        span{
+            class:"perdiodbutton",
+            onclick: move |_event|{
+                year.set(year.get() + 1);
+            },
+            Icon{
+                width: 24,
+                height: 24,
+                icon: IoArrowForwardCircleOutline,
+            }
+        },
+        button{
+            class:"menu",
+            onclick: move |_event|{
+                let file_name: String = format!("{} {}.csv", name, summary.year)
+                    .chars()
+                    .map(|c| if c.is_alphanumeric() || c == '.' { c } else { '_' })
+                    .collect();
+                let path = report::export_path(&file_name);
+                match summary.write_csv(&name, &path){
+                    Ok(()) => message.set(format!("Exported to {}", path.display())),
+                    Err(e) => message.set(format!("Error exporting: {}", e)),
+                }
+            },
+            "Export csv"
+        },
+        p{
+            "{message}"
+        },
+        table{
+            class:"grid",
+            tr{
+                th{"Month"},
+                th{"Hours"},
+                th{"Days"},
+                th{"Average day"},
+                th{"Longest session"},
+            }
+            for month in months.into_iter().chain(std::iter::once(total)){
+                tr{
+                    td{"{month.label}"},
+                    td{"{month.hours}"},
+                    td{"{month.days}"},
+                    td{"{month.average}"},
+                    td{"{month.longest}"},
+                }
+            }
+        }
+    })
+}
+
+/// Period given by the `year` and `month` segments of the route, if present.
+fn route_period(route: &dioxus_router::UseRoute) -> Option<Period> {
+    let year = route.segment("year")?.parse().ok()?;
+    let month = route.segment("month")?.parse().ok()?;
+    Some(Period::new(year, month))
+}
+
 fn user_view<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
     let route = use_route(cx);
-    let name = route.segment("name").unwrap_or("?");
+    let router = use_router(cx);
+    let user_id = route_user_id(route);
+    let name = cx.props.hours_data.read().display_name(user_id);
     let hours_data = cx.props.hours_data;
+    let settings = cx.props.settings;
     let mode = cx.props.mode;
     let year = cx.props.year;
     let month = cx.props.month;
-    let period = use_state(cx, || Period::new(year, month));
+    let period = use_state(cx, || route_period(route).unwrap_or(Period::new(year, month)));
+    // Day picked in the calendar, 0 to list the whole month.
+    let day = use_state(cx, || 0u32);
+    let year_calendar = use_state(cx, || false);
+    let calendar_year = period.get().year;
+    let pin = use_state(cx, || "".to_string());
+    let message = use_state(cx, || "".to_string());
+    let pin_required = settings.read().effective().pin_required;
+    let needs_pin = !mode.read().can_edit_user(&hours_data.read(), user_id) && hours_data.read().needs_pin(user_id, pin_required);
+    let clock_state = hours_data.read().clock_state(user_id);
+    let today = hours_data
+        .read()
+        .dataframe
+        .hours_for_day(user_id, chrono::Local::now().date_naive());
+    let tick = use_state(cx, || 0u64);
+    // Redraws the running durations and totals every second while the user is working.
+    use_future(cx, (&user_id,), |(user_id,)| {
+        let hours_data = hours_data.clone();
+        let tick = tick.clone();
+        async move {
+            loop {
+                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                if let ClockState::Working { .. } = hours_data.read().clock_state(user_id) {
+                    tick.modify(|t| t + 1);
+                }
+            }
+        }
+    });
+    let (stale, open_since) = match clock_state {
+        ClockState::Stale { index } => {
+            let record = hours_data.read().dataframe.record(index).cloned().unwrap_or_default();
+            (true, format!("{} {}", record.date(), record.start_time()))
+        }
+        _ => (false, "".to_string()),
+    };
+    // Checks the PIN, then starts or ends; errors are shown below the buttons.
+    let clock = move |action: ClockAction| {
+        let settings = settings.read().effective();
+        let checked = mode.write().check_pin(&hours_data.read(), &settings, user_id, pin.get());
+        pin.set("".to_string());
+        let result = checked.and_then(|_| {
+            let mut hours_data = hours_data.write();
+            match action {
+                ClockAction::Start => hours_data.start(user_id),
+                ClockAction::End => hours_data.end(user_id),
+                ClockAction::CloseStale => hours_data.close_stale(user_id),
+                ClockAction::CloseStaleAndStart => hours_data.close_stale_and_start(user_id),
+            }
+        });
+        match result {
+            Ok(()) => message.set("".to_string()),
+            Err(e) => message.set(e.to_string()),
+        }
+    };
 
     cx.render(rsx! {
         div{
             page_title{
                 mode: mode,
-                title_text: name.to_string(),
+                title_text: name,
+            },
+            conflict_view{
+                hours_data: hours_data,
+                mode: mode,
+                settings: settings,
             },
             span{
                 class:"period",
@@ -279,6 +2175,7 @@ fn user_view<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
                     let new_period = period.get().previous();
                     if true || new_period>=hours_data.read().dataframe.first_period(){
                         period.set(new_period);
+                        day.set(0);
                     }
                 },
                 Icon{
@@ -293,6 +2190,7 @@ fn user_view<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
                     let new_period = period.get().next();
                     if true || new_period<=Period::current() || new_period<=hours_data.read().dataframe.last_period(){
                         period.set(new_period);
+                        day.set(0);
                     }
                 },
                 Icon{
@@ -301,6 +2199,13 @@ fn user_view<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
                     icon: IoArrowForwardCircleOutline,
                 }
             },
+            button{
+                class:"menu",
+                onclick: move |_event|{
+                    router.navigate_to(&format!("/year/{}", user_id));
+                },
+                "Year summary"
+            },
             span{
                 class:"e",
             },
@@ -310,7 +2215,18 @@ fn user_view<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
             },
             span{
                 class:"b",
-                "{hours_data.read().dataframe.status_for_period(name, period)}"
+                "{hours_data.read().dataframe.status_for_period(user_id, period)}"
+            },
+            span{
+                class:"e",
+            },
+            span{
+                class:"a",
+                "Today"
+            },
+            span{
+                class:"b",
+                "{today}"
             },
             span{
                 class:"e",
@@ -321,38 +2237,41 @@ fn user_view<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
             },
             span{
                 class:"b",
-                "{hours_data.read().dataframe.hours_for_period(name, period):02}"
+                "{hours_data.read().dataframe.hours_for_period(user_id, period):02}"
             },
             span{
                 class:"e",
             },
-            if hours_data.read().is_started(name){
-                rsx!{button{
-                    class:"menu",
-                    onclick: move |_event|{
-                        let mut hours_data = hours_data.write();
-                        hours_data.end(name).unwrap_or_else(|e|{
-                            println!("Error ending period: {}", e);
-                        });
+            if needs_pin && !hours_data.read().is_read_only(){
+                rsx!{
+                    span{
+                        class:"a",
+                        "PIN"
+                    },
+                    input{
+                        class: "password",
+                        "type": "password",
+                        value: "{pin}",
+                        oninput: move |event|{
+                            pin.set(event.value.clone());
+                        },
                     },
                     span{
-                        class:"icon1",
-                        Icon{
-                            width: 20,
-                            height: 20,
-                            icon: IoStopCircleOutline,
-                        }
-                    }
-                    "End"
+                        class:"e",
+                    },
+                }
+            }
+            if hours_data.read().is_read_only(){
+                rsx!{span{
+                    class:"error",
+                    "Read-only, the data failed to load"
                 }}
-            }else{
+            }
+            else if clock_state == ClockState::Idle{
                 rsx!{button{
                     class:"menu",
                     onclick: move |_event|{
-                        let mut hours_data = hours_data.write();
-                        hours_data.start(name).unwrap_or_else(|e|{
-                            println!("Error starting period: {}", e);
-                        });
+                        clock(ClockAction::Start);
                     },
                     span{
                         class:"icon1",
@@ -364,12 +2283,121 @@ fn user_view<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
                     }
                     "Start"
                 }}
+            }
+            else{
+                rsx!{
+                    if stale{
+                        rsx!{
+                            span{
+                                class:"error",
+                                "Started on {open_since} and not ended. Close it without hours, an admin then enters the hours worked."
+                            },
+                            span{
+                                class:"e",
+                            },
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    clock(ClockAction::CloseStale);
+                                },
+                                "Close without hours"
+                            }
+                            button{
+                                class:"menu",
+                                onclick: move |_event|{
+                                    clock(ClockAction::CloseStaleAndStart);
+                                },
+                                "Close and start"
+                            }
+                        }
+                    }
+                    else{
+                        rsx!{button{
+                            class:"menu",
+                            onclick: move |_event|{
+                                clock(ClockAction::End);
+                            },
+                            span{
+                                class:"icon1",
+                                Icon{
+                                    width: 20,
+                                    height: 20,
+                                    icon: IoStopCircleOutline,
+                                }
+                            }
+                            "End"
+                        }}
+                    }
+                }
+            },
+            span{
+                class:"error",
+                "{message}"
             },
             br{},
+            button{
+                class:"menu",
+                onclick: move |_event|{
+                    year_calendar.set(!*year_calendar.get());
+                },
+                if *year_calendar.get(){
+                    rsx!{"Month calendar"}
+                }
+                else{
+                    rsx!{"Year calendar"}
+                }
+            },
+            if *day.get() != 0{
+                rsx!{button{
+                    class:"menu",
+                    onclick: move |_event|{
+                        day.set(0);
+                    },
+                    "Show all days"
+                }}
+            }
+            div{
+                class:"calendars",
+                if *year_calendar.get(){
+                    rsx!{
+                        for m in 1..=12u32{
+                            month_calendar{
+                                key: "{m}",
+                                hours_data: hours_data,
+                                user_id: user_id,
+                                month: m,
+                                year: calendar_year,
+                                selected: if period.get().month == m { *day.get() } else { 0 },
+                                onselect: move |d|{
+                                    period.set(Period::new(calendar_year, m));
+                                    day.set(d);
+                                    year_calendar.set(false);
+                                },
+                            }
+                        }
+                    }
+                }
+                else{
+                    rsx!{
+                        month_calendar{
+                            hours_data: hours_data,
+                            user_id: user_id,
+                            month: period.get().month,
+                            year: period.get().year,
+                            selected: *day.get(),
+                            onselect: move |d|{
+                                day.set(d);
+                            },
+                        }
+                    }
+                }
+            }
             period_overview{
                 hours_data: hours_data,
+                settings: settings,
                 month: period.get().month,
                 year: period.get().year,
+                day: *day.get(),
                 mode: mode,
             }
         }
@@ -412,15 +2440,21 @@ pub fn period_entry(cx: Scope, record: HoursRecord) -> Element {
 pub struct PeriodEntryProps<'a> {
     pub hours_data: &'a UseRef<HoursData>,
     pub mode: &'a UseRef<Mode>,
-    pub i: usize,
+    /// Index of the record, see [model::HoursDataFrame::new_index].
+    pub index: isize,
 }
 
 pub fn edit_period_entry<'a>(cx: Scope<'a, PeriodEntryProps<'a>>) -> Element {
     let edit_field = use_state(cx, || false);
+    let input = use_state(cx, || "".to_string());
+    let input_error = use_state(cx, || "".to_string());
     let mode = cx.props.mode;
     let hours_data = cx.props.hours_data;
-    let i = cx.props.i;
-    let record = hours_data.read().dataframe.data[i].clone();
+    let index = cx.props.index;
+    let record = match hours_data.read().dataframe.record(index).cloned() {
+        Some(record) => record,
+        None => return None,
+    };
 
     cx.render(rsx! {
         div{
@@ -440,24 +2474,38 @@ pub fn edit_period_entry<'a>(cx: Scope<'a, PeriodEntryProps<'a>>) -> Element {
                 class:"c",
                 record.original_hours()
             }
-            if mode.read().is_admin(){
+            if mode.read().can_edit_user(&hours_data.read(), record.user_id) && !hours_data.read().is_read_only(){
                 if *edit_field.get(){
-                    let value = record.hours.clone();
                     rsx!{
                         input{
-                            value: "{value}",
+                            value: "{input}",
+                            placeholder: "7:30, 7,5 or 7h 30m",
                             oninput: move |event|{
-                                let mut hours_data = hours_data.write();
-                                hours_data.dataframe.data[i].hours = event.value.to_string();
+                                input_error.set(match Hours::normalize(&event.value){
+                                    Ok(_) => "".to_string(),
+                                    Err(e) => e.to_string(),
+                                });
+                                input.set(event.value.clone());
                             },
-                            onkeypress: move |event|{
+                            onkeydown: move |event|{
                                 if event.key()==Key::Enter{
-                                    edit_field.set(false);
-                                    hours_data.read().save().unwrap_or_else(|e|{
-                                        println!("Error editing field {}: {}", i, e);
+                                    let result = mode.read().require_access().and_then(|access|{
+                                        hours_data.write().set_hours(&access, index, input.get())
                                     });
+                                    match result{
+                                        Ok(()) => edit_field.set(false),
+                                        Err(e) => input_error.set(e.to_string()),
+                                    }
+                                }
+                                else if event.key()==Key::Escape{
+                                    input_error.set("".to_string());
+                                    edit_field.set(false);
                                 }
                             },
+                        },
+                        span{
+                            class:"error",
+                            "{input_error}"
                         }
                     }
                 }
@@ -470,6 +2518,9 @@ pub fn edit_period_entry<'a>(cx: Scope<'a, PeriodEntryProps<'a>>) -> Element {
                         span{
                             class:"icon",
                             onclick: move |_event|{
+                                let hours = hours_data.read().dataframe.record(index).map(|r| r.hours.clone());
+                                input.set(hours.unwrap_or_default());
+                                input_error.set("".to_string());
                                 edit_field.set(true);
                             },
                             Icon{
@@ -496,30 +2547,60 @@ pub fn edit_period_entry<'a>(cx: Scope<'a, PeriodEntryProps<'a>>) -> Element {
     })
 }
 
+#[derive(Clone, Copy)]
+enum ClockAction {
+    Start,
+    End,
+    CloseStale,
+    CloseStaleAndStart,
+}
+
 #[derive(Props)]
 pub struct PeriodProps<'a> {
     pub hours_data: &'a UseRef<HoursData>,
     pub mode: &'a UseRef<Mode>,
+    pub settings: &'a UseRef<SettingsStore>,
     pub month: u32,
     pub year: i32,
+    /// Day of the month to list alone, 0 for the whole month.
+    #[props(default)]
+    pub day: u32,
 }
 
 fn period_overview<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
     let route = use_route(cx);
-    let name = route.segment("name").unwrap();
+    let user_id = route_user_id(route);
     let hours_data = cx.props.hours_data;
     let mode = cx.props.mode;
     let month = cx.props.month;
     let year = cx.props.year;
+    let day = cx.props.day;
     let index = hours_data
         .read()
         .dataframe
         .data
         .iter()
-        .enumerate()
-        .filter(|(_i, x)| (x.name == name) && (x.year == year) && (x.month == month))
-        .map(|(i, _x)| i)
-        .collect::<Vec<usize>>();
+        .filter(|x| (x.user_id == user_id) && (x.year == year) && (x.month == month))
+        .filter(|x| day == 0 || x.start_dt().map(|s| s.day() == day).unwrap_or(false))
+        .map(|x| x.index)
+        .collect::<Vec<isize>>();
+    let period = Period::new(year, month);
+    let day_labels = (1..=period.days()).map(|d| d.to_string()).collect::<Vec<_>>();
+    let day_chart = chart::bar_chart(
+        &day_labels,
+        &hours_data.read().dataframe.hours_by_day(user_id, &period),
+    );
+    // the twelve months up to the selected one
+    let mut months = vec![period];
+    while months.len() < 12 {
+        months.insert(0, months[0].previous());
+    }
+    let month_labels = months.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+    let month_hours = months
+        .iter()
+        .map(|p| hours_data.read().dataframe.hours_for_period(user_id, p))
+        .collect::<Vec<_>>();
+    let trend_chart = chart::line_chart(&month_labels, &month_hours);
 
     cx.render(rsx! {
         div{
@@ -550,42 +2631,170 @@ fn period_overview<'a>(cx: Scope<'a, PeriodProps<'a>>) -> Element {
         div{
             for i in index.iter(){
                 edit_period_entry{
+                    key: "{i}",
                     hours_data: hours_data,
                     mode: mode,
-                    i: *i,
+                    index: *i,
                 }
                 //period_entry{record: record.clone()}
             }
         }
+        div{
+            class:"chart",
+            dangerous_inner_html: "{day_chart}",
+        }
+        div{
+            class:"chart",
+            dangerous_inner_html: "{trend_chart}",
+        }
+    })
+}
+
+#[derive(Props)]
+pub struct CalendarProps<'a> {
+    pub hours_data: &'a UseRef<HoursData>,
+    pub user_id: u64,
+    pub month: u32,
+    pub year: i32,
+    /// Highlighted day, 0 for none.
+    pub selected: u32,
+    pub onselect: EventHandler<'a, u32>,
+}
+
+/// Month as a calendar with the days shaded by the hours worked.
+fn month_calendar<'a>(cx: Scope<'a, CalendarProps<'a>>) -> Element {
+    let period = Period::new(cx.props.year, cx.props.month);
+    let days = report::month_days(&cx.props.hours_data.read().dataframe, cx.props.user_id, &period);
+    let offset = period
+        .date(1)
+        .map(|d| d.weekday().num_days_from_monday() as usize)
+        .unwrap_or(0);
+    // (day, class, hover text) of each cell; day 0 fills the weeks before the first and after the last day
+    let mut cells = vec![(0, "".to_string(), "".to_string()); offset];
+    for d in days.into_iter() {
+        let mut class = format!("heat l{}", report::shade(d.hours));
+        if d.day == cx.props.selected {
+            class.push_str(" selected");
+        }
+        let title = if d.first_start.is_empty() {
+            "No records".to_string()
+        } else if d.last_end.is_empty() {
+            format!("{} - ..., {}", d.first_start, d.hours)
+        } else {
+            format!("{} - {}, {}", d.first_start, d.last_end, d.hours)
+        };
+        cells.push((d.day, class, title));
+    }
+    while cells.len() % 7 != 0 {
+        cells.push((0, "".to_string(), "".to_string()));
+    }
+    let weeks = cells.chunks(7).map(|w| w.to_vec()).collect::<Vec<_>>();
+
+    cx.render(rsx! {
+        table{
+            class:"calendar",
+            tr{
+                th{
+                    colspan: "7",
+                    "{period}"
+                }
+            }
+            tr{
+                for name in ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"]{
+                    th{
+                        "{name}"
+                    }
+                }
+            }
+            for week in weeks.into_iter(){
+                tr{
+                    for (day, class, title) in week.into_iter(){
+                        if day == 0{
+                            rsx!{td{}}
+                        }
+                        else{
+                            rsx!{td{
+                                class:"{class}",
+                                title:"{title}",
+                                onclick: move |_event|{
+                                    cx.props.onselect.call(day);
+                                },
+                                "{day}"
+                            }}
+                        }
+                    }
+                }
+            }
+        }
     })
 }
 
 pub fn app(cx: Scope) -> Element {
-    let hours_data = use_ref(cx, || {
-        HoursData::load().unwrap_or_else(|e| {
-            println!("Error loading data: {}", e);
-            HoursData::default()
-        })
+    let settings = use_ref(cx, || {
+        let args: Vec<String> = std::env::args().collect();
+        SettingsStore::load_or_default(&args)
     });
-    let mode = use_ref(cx, || Mode::default());
-    let _names = hours_data.read().names.clone();
-    //let names = data.names.clone();
+    let hours_data = use_ref(cx, || match settings.read().error.clone() {
+        Some(e) => HoursData::without_settings(&e),
+        None => HoursData::load_or_safe_mode(&settings.read().effective()),
+    });
+    let mode = use_ref(cx, || Mode::new(&settings.read().effective()));
     let period = Period::current();
+    if let Some(error) = settings.read().error.clone() {
+        return cx.render(rsx! {
+            style{
+                include_str!("../src/style.css")
+            },
+            div{
+                class:"error",
+                p{
+                    "The settings could not be read, so the data folder is not known. No data is loaded or saved."
+                },
+                p{
+                    "{error}"
+                },
+                p{
+                    "Correct the settings file or the HOURRS_ environment variables and command line options, then restart the app."
+                }
+            }
+        });
+    }
     cx.render(rsx! {
         style{
             include_str!("../src/style.css")
         },
-        Router{
-            /*
-            ul{
-                li{Link{to: "/names", "Names"}},
-            }
-            */
-            //Route{to: "/names", show_names{names: names}},
-            //Redirect{to: "/names"},
-            Route{to: "/user/:name", user_view{hours_data: hours_data, mode: mode, year: period.year, month: period.month}},
-            Route{to: "/names", users_page{hours_data: hours_data, mode: mode}},
-            Route{to: "/", users_page{hours_data: hours_data, mode: mode}},
+        // Any click or key press keeps the login alive.
+        div{
+            onclick: move |_event|{
+                mode.write_silent().touch();
+            },
+            onkeydown: move |_event|{
+                mode.write_silent().touch();
+            },
+            Router{
+                /*
+                ul{
+                    li{Link{to: "/names", "Names"}},
+                }
+                */
+                //Route{to: "/names", show_names{names: names}},
+                //Redirect{to: "/names"},
+                Route{to: "/user/:id/:year/:month", user_view{hours_data: hours_data, mode: mode, settings: settings, year: period.year, month: period.month}},
+                Route{to: "/year/:id", year_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/team", team_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/user/:id", user_view{hours_data: hours_data, mode: mode, settings: settings, year: period.year, month: period.month}},
+                Route{to: "/orphans", orphans_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/health", health_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/profile/:id", profile_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/names", users_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/backups", backups_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/setup", setup_page{mode: mode}},
+                Route{to: "/password", password_page{mode: mode}},
+                Route{to: "/accounts", accounts_page{mode: mode}},
+                Route{to: "/history", history_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/settings", settings_page{hours_data: hours_data, mode: mode, settings: settings}},
+                Route{to: "/", users_page{hours_data: hours_data, mode: mode, settings: settings}},
+            }
         }
     })
 }
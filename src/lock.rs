@@ -0,0 +1,122 @@
+use crate::backup::DATA_FILES;
+use anyhow::{anyhow, Result};
+use fs2::FileExt;
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::{Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Scripts writing to the data folder should hold an exclusive lock (flock) on this file.
+pub const LOCK_FILE: &str = "hours.lock";
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Advisory exclusive lock on the data folder, held while the data is read or written.
+/// The lock is released when dropped.
+#[derive(Debug)]
+pub struct FolderLock {
+    file: File,
+}
+
+impl FolderLock {
+    pub fn acquire<P: AsRef<Path>>(folder: P) -> Result<FolderLock> {
+        let folder = folder.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(folder.join(LOCK_FILE))?;
+        let start = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(FolderLock { file }),
+                Err(e) if start.elapsed() > LOCK_TIMEOUT => {
+                    return Err(anyhow!(
+                        "Data in {:?} is locked by another process: {}",
+                        folder,
+                        e
+                    ));
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    }
+}
+
+impl Drop for FolderLock {
+    fn drop(&mut self) {
+        self.file.unlock().unwrap_or_else(|e| {
+            println!("Error unlocking data folder: {}", e);
+        });
+    }
+}
+
+/// Modification time and content hash of the data files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint(Vec<Option<(SystemTime, u64)>>);
+
+impl Fingerprint {
+    pub fn of<P: AsRef<Path>>(folder: P) -> Result<Fingerprint> {
+        let mut files = Vec::new();
+        for file in DATA_FILES {
+            let path = folder.as_ref().join(file);
+            if path.exists() {
+                let modified = std::fs::metadata(&path)?.modified()?;
+                let mut hasher = DefaultHasher::new();
+                hasher.write(&std::fs::read(&path)?);
+                files.push(Some((modified, hasher.finish())));
+            } else {
+                files.push(None);
+            }
+        }
+        Ok(Fingerprint(files))
+    }
+}
+
+/// Error returned when saving data that has been changed by another process since it was loaded.
+#[derive(Debug, Clone)]
+pub struct ExternalModification {
+    pub folder: PathBuf,
+}
+
+impl Display for ExternalModification {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Data in {:?} was modified by another program since it was loaded",
+            self.folder
+        )
+    }
+}
+
+impl std::error::Error for ExternalModification {}
+
+/// Remembers the fingerprint of the data files as last loaded or saved by this process.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeDetector {
+    fingerprint: RefCell<Option<Fingerprint>>,
+}
+
+impl ChangeDetector {
+    pub fn remember<P: AsRef<Path>>(&self, folder: P) -> Result<()> {
+        *self.fingerprint.borrow_mut() = Some(Fingerprint::of(folder)?);
+        Ok(())
+    }
+
+    /// Fails with [ExternalModification] if the files differ from the remembered state.
+    /// Nothing is checked before the data has been loaded or saved once.
+    pub fn check<P: AsRef<Path>>(&self, folder: P) -> Result<()> {
+        if let Some(known) = self.fingerprint.borrow().as_ref() {
+            if Fingerprint::of(folder.as_ref())? != *known {
+                return Err(ExternalModification {
+                    folder: folder.as_ref().to_path_buf(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,19 @@
 #![allow(non_snake_case)]
 // import the prelude to get access to the `rsx!` macro and the `Scope` and `Element` types
 use dioxus::prelude::*;
-use hourrs_classic::app;
+use hourrs_classic::{app, convert_storage};
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--convert-to") {
+        // one-shot conversion of the data folder to another storage backend
+        let to = args.get(i + 1).cloned().unwrap_or_default();
+        if let Err(e) = convert_storage(&args, &to) {
+            eprintln!("Conversion failed: {}", e);
+            std::process::exit(1);
+        }
+        println!("Converted to {}", to);
+        return;
+    }
     hot_reload_init!();
     // launch the dioxus app in a webview
     dioxus_desktop::launch(app);
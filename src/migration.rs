@@ -0,0 +1,441 @@
+use crate::backup::{self, atomic_write};
+use crate::journal::JOURNAL_FILE;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Version of the on-disk format written by this build.
+/// It is raised when the files change shape, and also when they only gain data that older builds
+/// would drop on their next save: [migrate] refuses newer versions, so an older build can not open
+/// and overwrite such data. Steps for the latter kind change nothing.
+pub const FORMAT_VERSION: u32 = 6;
+
+pub const DATAFRAME_FILE: &str = "hours_dataframe.json";
+/// Plain list of names, replaced by [USERS_FILE] in version 4.
+pub const NAMES_FILE: &str = "hours_names.json";
+pub const USERS_FILE: &str = "hours_users.json";
+pub const META_FILE: &str = "hours_meta.json";
+
+/// Content of `hours_meta.json`.
+/// Folders written before the format was versioned have no meta file and are treated as version 1.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Meta {
+    pub format_version: u32,
+}
+
+impl Default for Meta {
+    fn default() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+        }
+    }
+}
+
+impl Meta {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Meta> {
+        let meta_path = path.as_ref().join(META_FILE);
+        if !meta_path.exists() {
+            return Ok(Meta { format_version: 1 });
+        }
+        let file = File::open(meta_path)?;
+        Ok(serde_json::from_reader(file)?)
+    }
+}
+
+/// Stored files as untyped json, so that migrations are not bound to the current structs.
+#[derive(Debug, Clone)]
+pub struct RawStore {
+    pub dataframe: Value,
+    /// Content of [NAMES_FILE], present up to version 3.
+    pub names: Option<Value>,
+    /// Content of [USERS_FILE], present from version 4.
+    pub users: Option<Value>,
+    /// Entries of the event journal, see [crate::journal].
+    pub journal: Vec<Value>,
+}
+
+impl RawStore {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<RawStore> {
+        let file = File::open(path.as_ref().join(DATAFRAME_FILE))?;
+        let dataframe = serde_json::from_reader(file)?;
+        let names_path = path.as_ref().join(NAMES_FILE);
+        let names = if names_path.exists() {
+            Some(serde_json::from_reader(File::open(names_path)?)?)
+        } else {
+            None
+        };
+        let users_path = path.as_ref().join(USERS_FILE);
+        let users = if users_path.exists() {
+            Some(serde_json::from_reader(File::open(users_path)?)?)
+        } else {
+            None
+        };
+        if names.is_none() && users.is_none() {
+            return Err(anyhow!("Neither {} nor {} found", NAMES_FILE, USERS_FILE));
+        }
+        let journal_path = path.as_ref().join(JOURNAL_FILE);
+        let mut journal = Vec::new();
+        if journal_path.exists() {
+            for line in std::fs::read_to_string(journal_path)?.lines() {
+                if !line.trim().is_empty() {
+                    journal.push(serde_json::from_str(line)?);
+                }
+            }
+        }
+        Ok(RawStore {
+            dataframe,
+            names,
+            users,
+            journal,
+        })
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        atomic_write(
+            path.as_ref().join(DATAFRAME_FILE),
+            serde_json::to_string_pretty(&self.dataframe)?,
+        )?;
+        let names_path = path.as_ref().join(NAMES_FILE);
+        match &self.names {
+            Some(names) => atomic_write(&names_path, serde_json::to_string_pretty(names)?)?,
+            None if names_path.exists() => std::fs::remove_file(&names_path)?,
+            None => {}
+        }
+        if let Some(users) = &self.users {
+            atomic_write(
+                path.as_ref().join(USERS_FILE),
+                serde_json::to_string_pretty(users)?,
+            )?;
+        }
+        let journal_path = path.as_ref().join(JOURNAL_FILE);
+        if self.journal.is_empty() {
+            if journal_path.exists() {
+                std::fs::remove_file(&journal_path)?;
+            }
+        } else {
+            let mut journal = String::new();
+            for entry in self.journal.iter() {
+                journal.push_str(&serde_json::to_string(entry)?);
+                journal.push('\n');
+            }
+            atomic_write(&journal_path, journal)?;
+        }
+        Ok(())
+    }
+
+    /// All stored records: those of the snapshot followed by those carried by journal events.
+    fn records_mut(&mut self) -> Result<Vec<&mut Value>> {
+        let mut records: Vec<&mut Value> = self
+            .dataframe
+            .get_mut("data")
+            .and_then(|d| d.as_array_mut())
+            .ok_or_else(|| anyhow!("{} has no data array", DATAFRAME_FILE))?
+            .iter_mut()
+            .collect();
+        records.extend(self.journal.iter_mut().filter_map(|e| e.get_mut("record")));
+        Ok(records)
+    }
+}
+
+type Migration = fn(&mut RawStore) -> Result<()>;
+
+/// Migration steps; `MIGRATIONS[i]` upgrades version `i + 1` to version `i + 2`.
+const MIGRATIONS: [Migration; 5] = [v1_to_v2, v2_to_v3, v3_to_v4, v4_to_v5, v5_to_v6];
+
+/// Version 1 files were written by the pandas based app, which stores missing strings as `null`.
+fn v1_to_v2(store: &mut RawStore) -> Result<()> {
+    for (i, record) in store.records_mut()?.into_iter().enumerate() {
+        let record = record
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("Record {} is not an object", i))?;
+        for field in ["name", "start", "end", "hours"] {
+            let value = record.entry(field).or_insert(Value::Null);
+            if value.is_null() {
+                *value = Value::String("".to_string());
+            }
+        }
+    }
+    match &store.names {
+        None | Some(Value::Null) => store.names = Some(Value::Array(Vec::new())),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Version 3 adds the event journal. The files are unchanged; the bump only makes older builds,
+/// which would ignore the journal and lose its events on their next save, refuse the folder.
+fn v2_to_v3(_store: &mut RawStore) -> Result<()> {
+    Ok(())
+}
+
+/// Version 4 replaces the list of names by user profiles with ids; records refer to the id.
+/// Name events of the journal are folded into the list first, as there are no ids for them.
+/// Records whose name is not in the list get the id 0.
+fn v3_to_v4(store: &mut RawStore) -> Result<()> {
+    let mut names: Vec<String> = match store.names.take() {
+        Some(names) => serde_json::from_value(names)?,
+        None => Vec::new(),
+    };
+    store.journal = fold_name_events(&mut names, std::mem::take(&mut store.journal))?;
+
+    for record in store.records_mut()? {
+        let name = record["name"].as_str().unwrap_or_default().to_string();
+        record["user_id"] = Value::from(user_id_for_name(&names, &name));
+    }
+    if let Some(fields) = store
+        .dataframe
+        .pointer_mut("/schema/fields")
+        .and_then(|f| f.as_array_mut())
+    {
+        if !fields.iter().any(|f| f["name"] == "user_id") {
+            let position = fields
+                .iter()
+                .position(|f| f["name"] == "name")
+                .map(|i| i + 1)
+                .unwrap_or(fields.len());
+            fields.insert(
+                position,
+                serde_json::json!({"name": "user_id", "type": "integer"}),
+            );
+        }
+    }
+    store.users = Some(Value::Array(
+        names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| serde_json::json!({"id": i + 1, "name": name}))
+            .collect(),
+    ));
+    Ok(())
+}
+
+/// Applies the name events of a version 3 journal to the list of names; returns the other entries.
+pub(crate) fn fold_name_events(names: &mut Vec<String>, journal: Vec<Value>) -> Result<Vec<Value>> {
+    let mut rest = Vec::new();
+    for entry in journal {
+        let event = entry.get("event").and_then(|e| e.as_str()).unwrap_or_default();
+        match event {
+            "NameAdded" => {
+                let name = entry["name"].as_str().unwrap_or_default().to_string();
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            "NameRemoved" => {
+                let name = entry["name"].as_str().unwrap_or_default();
+                names.retain(|n| n != name);
+            }
+            "NamesReordered" => {
+                *names = serde_json::from_value(entry["names"].clone())?;
+            }
+            _ => rest.push(entry),
+        }
+    }
+    Ok(rest)
+}
+
+/// Id of the user with the name, given by position in the list of names of version 3;
+/// 0 if the name is not in the list.
+pub(crate) fn user_id_for_name(names: &[String], name: &str) -> u64 {
+    names
+        .iter()
+        .position(|n| n == name)
+        .map(|i| i as u64 + 1)
+        .unwrap_or(0)
+}
+
+/// Version 5 adds the archived flag of users, which defaults to false. Nothing needs converting;
+/// the bump only makes older builds, which would drop the flag on their next save, refuse the folder.
+fn v4_to_v5(_store: &mut RawStore) -> Result<()> {
+    Ok(())
+}
+
+/// Version 6 adds the hashed PIN of users, which defaults to no PIN. Nothing needs converting;
+/// the bump only makes older builds, which would drop the PIN on their next save and so unlock
+/// the profile, refuse the folder.
+fn v5_to_v6(_store: &mut RawStore) -> Result<()> {
+    Ok(())
+}
+
+/// Copies the stored files into a new `migration-v<version>-<timestamp>` backup, see [backup::create].
+pub fn backup<P: AsRef<Path>>(path: P, version: u32) -> Result<PathBuf> {
+    Ok(backup::create(path, &format!("migration-v{}-", version))?.path)
+}
+
+/// Upgrades the store in `path` to [FORMAT_VERSION] step by step.
+/// The original files are backed up first. Returns true if anything was migrated.
+pub fn migrate<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let path = path.as_ref();
+    let version = Meta::read(path)?.format_version;
+    if version > FORMAT_VERSION {
+        return Err(anyhow!(
+            "Data in {:?} has format version {}, this version only supports up to {}",
+            path,
+            version,
+            FORMAT_VERSION
+        ));
+    }
+    if version == FORMAT_VERSION {
+        return Ok(false);
+    }
+    if version == 0 {
+        return Err(anyhow!("Invalid format version 0 in {:?}", path));
+    }
+    let backup_path = backup(path, version)?;
+    println!("Migrating {:?} from version {}, backup in {:?}", path, version, backup_path);
+
+    let mut store = RawStore::read(path)?;
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        step(&mut store).map_err(|e| anyhow!("Migration to version {} failed: {}", i + 2, e))?;
+    }
+    store.write(path)?;
+    atomic_write(
+        path.join(META_FILE),
+        serde_json::to_string_pretty(&Meta::default())?,
+    )?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{HoursDataFrame, Users};
+    use crate::test_util::fixture;
+
+    fn users(path: &Path) -> Users {
+        serde_json::from_reader(File::open(path.join(USERS_FILE)).unwrap()).unwrap()
+    }
+
+    fn user_ids(path: &Path) -> Vec<(String, u64)> {
+        let dataframe: HoursDataFrame =
+            serde_json::from_reader(File::open(path.join(DATAFRAME_FILE)).unwrap()).unwrap();
+        dataframe
+            .data
+            .into_iter()
+            .map(|r| (r.name, r.user_id))
+            .collect()
+    }
+
+    fn backups(path: &Path) -> Vec<String> {
+        std::fs::read_dir(path.join(backup::BACKUP_FOLDER))
+            .map(|entries| {
+                entries
+                    .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+                    .filter(|name| name.starts_with("migration-v"))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn assert_migrated(path: &Path, version: u32) {
+        assert_eq!(Meta::read(path).unwrap().format_version, FORMAT_VERSION);
+        assert!(!path.join(NAMES_FILE).exists());
+        let backups = backups(path);
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].starts_with(&format!("migration-v{}-", version)));
+    }
+
+    #[test]
+    fn v1() {
+        let dir = fixture("v1");
+        assert!(migrate(dir.path()).unwrap());
+        assert_migrated(dir.path(), 1);
+        assert!(dir
+            .path()
+            .join(backup::BACKUP_FOLDER)
+            .join(&backups(dir.path())[0])
+            .join(NAMES_FILE)
+            .exists());
+        let names = users(dir.path())
+            .into_iter()
+            .map(|u| (u.name, u.id))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![("Anna".to_string(), 1), ("Bob".to_string(), 2)]);
+        assert_eq!(
+            user_ids(dir.path()),
+            vec![("Anna".to_string(), 1), ("Bob".to_string(), 2), ("Zed".to_string(), 0)]
+        );
+        let dataframe: HoursDataFrame =
+            serde_json::from_reader(File::open(dir.path().join(DATAFRAME_FILE)).unwrap()).unwrap();
+        assert_eq!(dataframe.data[1].end, "");
+        assert_eq!(dataframe.data[1].hours, "");
+        assert_eq!(dataframe.data[2].hours, "1.5");
+        assert_eq!(dataframe.schema.fields[3].name, "user_id");
+    }
+
+    #[test]
+    fn v2() {
+        let dir = fixture("v2");
+        assert!(migrate(dir.path()).unwrap());
+        assert_migrated(dir.path(), 2);
+        assert_eq!(users(dir.path()).len(), 2);
+        assert_eq!(
+            user_ids(dir.path()),
+            vec![("Anna".to_string(), 1), ("Bob".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn v3_folds_name_events() {
+        let dir = fixture("v3");
+        assert!(migrate(dir.path()).unwrap());
+        assert_migrated(dir.path(), 3);
+        let names = users(dir.path())
+            .into_iter()
+            .map(|u| (u.name, u.id))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![("Carl".to_string(), 1), ("Anna".to_string(), 2)]);
+        assert_eq!(
+            user_ids(dir.path()),
+            vec![("Anna".to_string(), 2), ("Bob".to_string(), 0)]
+        );
+        let journal = crate::journal::read(dir.path().join(JOURNAL_FILE)).unwrap();
+        assert_eq!(journal.len(), 1);
+        match &journal[0].event {
+            crate::journal::Event::Started { record } => {
+                assert_eq!((record.name.as_str(), record.user_id), ("Carl", 1))
+            }
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn v4_keeps_users() {
+        let dir = fixture("v4");
+        assert!(migrate(dir.path()).unwrap());
+        assert_migrated(dir.path(), 4);
+        let users = users(dir.path());
+        assert_eq!(users.len(), 2);
+        assert!(!users[1].archived);
+        assert_eq!(users[1].pin_hash, "");
+        assert_eq!(
+            user_ids(dir.path()),
+            vec![("Anna".to_string(), 1), ("Bob".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn current_version_is_not_migrated() {
+        let dir = fixture("v6");
+        assert!(!migrate(dir.path()).unwrap());
+        assert!(backups(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn unsupported_versions_are_rejected() {
+        for version in [0, FORMAT_VERSION + 1] {
+            let dir = fixture("v6");
+            std::fs::write(
+                dir.path().join(META_FILE),
+                format!("{{\"format_version\": {}}}", version),
+            )
+            .unwrap();
+            assert!(migrate(dir.path()).is_err());
+            assert!(backups(dir.path()).is_empty());
+            assert_eq!(Meta::read(dir.path()).unwrap().format_version, version);
+        }
+    }
+}
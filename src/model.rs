@@ -1,15 +1,28 @@
 #![allow(dead_code)]
+use crate::migration::{self, Meta, DATAFRAME_FILE, META_FILE, USERS_FILE};
+use crate::auth::{self, Access};
+use crate::backup::atomic_write;
+use crate::health::{self, Issue};
+use crate::journal::Event;
+use crate::lock::ExternalModification;
+use crate::recovery;
+use crate::settings::Settings;
+use crate::storage::{self, Storage};
 use anyhow::{anyhow, Result};
 use chrono::{Datelike, Local, Timelike};
 use serde::{Deserialize, Serialize};
 
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::fs::File;
 use std::iter::Sum;
-use std::ops::{Add, Sub};
+use std::ops::{Add, Neg, Sub};
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
 
-const MAX_SECONDS: i64 = 60*60*15;
+/// Longest duration of a record; a record open for longer was probably not ended.
+pub(crate) const MAX_SECONDS: i64 = 60*60*15;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Field {
@@ -38,32 +51,161 @@ impl Display for Period {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
-pub struct Hours(f64);
+/// A duration in whole seconds, so that sums of many records are exact.
+/// It is shown as `HH:MM` rounded to minutes and stored as decimal hours.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hours {
+    seconds: i64,
+}
 
 impl Display for Hours {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let h = self.0.floor();
-        let m = ((self.0 - h) * 60.0).trunc();
-        write!(f, "{:02}:{:02}", h, m)
+        let minutes = self.minutes();
+        let sign = if minutes < 0 { "-" } else { "" };
+        write!(f, "{}{:02}:{:02}", sign, minutes.abs() / 60, minutes.abs() % 60)
     }
 }
 
 impl Hours {
+    /// Rounded to whole seconds.
     pub fn new(h: f64) -> Self {
-        Self(h)
+        Self::from_hours(h)
+    }
+    /// Rounded to whole seconds.
+    pub fn from_hours(hours: f64) -> Self {
+        Self {
+            seconds: (hours * 3600.0).round() as i64,
+        }
+    }
+    pub fn from_minutes(minutes: i64) -> Self {
+        Self {
+            seconds: minutes * 60,
+        }
+    }
+    pub fn from_seconds(seconds: i64) -> Self {
+        Self { seconds }
+    }
+    pub fn seconds(&self) -> i64 {
+        self.seconds
+    }
+    /// Rounded to the nearest minute, halves away from zero.
+    pub fn minutes(&self) -> i64 {
+        (self.seconds + self.seconds.signum() * 30) / 60
+    }
+    pub fn as_hours(&self) -> f64 {
+        self.seconds as f64 / 3600.0
+    }
+    pub fn is_negative(&self) -> bool {
+        self.seconds < 0
+    }
+    /// Decimal value as stored in [HoursRecord::hours], rounded to minutes.
+    pub fn to_decimal_string(&self) -> String {
+        format!("{}", self.minutes() as f64 / 60.0)
+    }
+    /// Reads decimal hours with dot or comma, including negative values, as written by older versions.
+    pub fn from_decimal_str(s: &str) -> Result<Self> {
+        let hours = s
+            .trim()
+            .replace(',', ".")
+            .parse::<f64>()
+            .map_err(|_| anyhow!("{} is not a decimal number", s))?;
+        if !hours.is_finite() {
+            return Err(anyhow!("{} is not a decimal number", s));
+        }
+        Ok(Self::from_hours(hours))
+    }
+    /// Checks an hours override as typed and returns it as stored; empty input removes the override.
+    pub fn normalize(input: &str) -> Result<String> {
+        if input.trim().is_empty() {
+            Ok("".to_string())
+        } else {
+            Ok(input.parse::<Hours>()?.to_decimal_string())
+        }
+    }
+}
+
+impl FromStr for Hours {
+    type Err = anyhow::Error;
+
+    /// Reads hours as `7.5`, `7,5`, `7:30`, `7h 30m`, `7h` or `45m`.
+    fn from_str(s: &str) -> Result<Self> {
+        let text: String = s
+            .to_lowercase()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let invalid = || {
+            anyhow!(
+                "Can not read hours \"{}\", use e.g. 7.5, 7,5, 7:30 or 7h 30m",
+                s.trim()
+            )
+        };
+        let decimal = |text: &str| text.replace(',', ".").parse::<f64>().map_err(|_| invalid());
+        let hours = if let Some((h, m)) = text.split_once(':') {
+            let h: u32 = h.parse().map_err(|_| invalid())?;
+            let m: u32 = m.parse().map_err(|_| invalid())?;
+            if m >= 60 {
+                return Err(invalid());
+            }
+            Hours::from_minutes(h as i64 * 60 + m as i64)
+        } else if text.contains('h') || text.contains('m') {
+            let text = text.replace("min", "m");
+            let (h, m) = text.split_once('h').unwrap_or(("", &text));
+            let m = m.strip_suffix('m').unwrap_or(m);
+            if h.is_empty() && m.is_empty() {
+                return Err(invalid());
+            }
+            let h = if h.is_empty() { 0.0 } else { decimal(h)? };
+            let m = if m.is_empty() { 0.0 } else { decimal(m)? };
+            Hours::from_hours(h + m / 60.0)
+        } else {
+            let hours = decimal(&text)?;
+            if !hours.is_finite() {
+                return Err(invalid());
+            }
+            Hours::from_hours(hours)
+        };
+        if hours.is_negative() || hours > Hours::from_minutes(24 * 60) {
+            return Err(anyhow!("Hours have to be between 0 and 24, not {}", s.trim()));
+        }
+        Ok(hours)
+    }
+}
+
+/// Serialized with [Hours::to_decimal_string] like [HoursRecord::hours];
+/// decimal strings, the formats of [Hours::from_str] and numbers are read.
+impl Serialize for Hours {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_decimal_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Hours {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Text(String),
+            Number(f64),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Text(text) => Hours::from_decimal_str(&text)
+                .or_else(|_| text.parse())
+                .map_err(serde::de::Error::custom),
+            Raw::Number(hours) => Ok(Hours::from_hours(hours)),
+        }
     }
 }
 
 impl From<f64> for Hours {
     fn from(h: f64) -> Self {
-        Self(h)
+        Self::from_hours(h)
     }
 }
 
 impl From<Hours> for f64 {
     fn from(h: Hours) -> Self {
-        h.0
+        h.as_hours()
     }
 }
 
@@ -77,7 +219,7 @@ impl Add for Hours {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self::Output {
-        Self(self.0 + rhs.0)
+        Self::from_seconds(self.seconds + rhs.seconds)
     }
 }
 
@@ -85,7 +227,15 @@ impl Sub for Hours {
     type Output = Self;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        Self(self.0 - rhs.0)
+        Self::from_seconds(self.seconds - rhs.seconds)
+    }
+}
+
+impl Neg for Hours {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::from_seconds(-self.seconds)
     }
 }
 
@@ -110,6 +260,20 @@ impl Period {
             Period::new(self.year, self.month + 1)
         }
     }
+    /// Number of days of the month.
+    pub fn days(&self) -> u32 {
+        let next = self.next();
+        match (
+            chrono::NaiveDate::from_ymd_opt(self.year, self.month, 1),
+            chrono::NaiveDate::from_ymd_opt(next.year, next.month, 1),
+        ) {
+            (Some(first), Some(next_first)) => (next_first - first).num_days() as u32,
+            _ => 0,
+        }
+    }
+    pub fn date(&self, day: u32) -> Option<chrono::NaiveDate> {
+        chrono::NaiveDate::from_ymd_opt(self.year, self.month, day)
+    }
     pub fn previous(&self) -> Period {
         if self.month == 1 {
             Period::new(self.year - 1, 12)
@@ -134,6 +298,7 @@ impl Default for Schema {
                 Field::new("index", "integer"),
                 Field::new("rowid", "integer"),
                 Field::new("name", "string"),
+                Field::new("user_id", "integer"),
                 Field::new("year", "integer"),
                 Field::new("month", "integer"),
                 Field::new("start", "string"),
@@ -146,11 +311,67 @@ impl Default for Schema {
     }
 }
 
+/// A user of the app. Records refer to users by the stable `id`;
+/// the name can change and does not have to be unique.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct UserProfile {
+    pub id: u64,
+    pub name: String,
+    pub employee_number: String,
+    pub email: String,
+    pub team: String,
+    pub contract: String,
+    pub weekly_hours: f64,
+    /// Archived users are hidden from the start page, their records are kept.
+    pub archived: bool,
+    /// Argon2 hash of the PIN asked before starting or ending; empty if the user has no PIN.
+    pub pin_hash: String,
+}
+
+impl UserProfile {
+    pub fn new(id: u64, name: &str) -> Self {
+        Self {
+            id,
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+pub type Users = Vec<UserProfile>;
+
+/// Records whose `user_id` does not belong to any user, grouped by id and name.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Orphan {
+    pub user_id: u64,
+    pub name: String,
+    pub records: usize,
+    pub first_date: String,
+    pub last_date: String,
+}
+
+/// Whether a user is working, derived from the records without end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockState {
+    /// No open record; the user can start.
+    Idle,
+    /// The record with `index` is open; the user can end.
+    Working { index: isize },
+    /// The record with `index` has been open for longer than a shift can last,
+    /// it was probably not ended. It has to be ended or closed before starting again.
+    Stale { index: isize },
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
 pub struct HoursRecord {
     pub index: isize,
     pub rowid: isize,
+    /// Name of the user when the record was created.
     pub name: String,
+    /// Id of the [UserProfile]; 0 if the record is not linked to a user.
+    #[serde(default)]
+    pub user_id: u64,
     pub year: i32,
     pub month: u32,
     pub start: String,
@@ -163,6 +384,7 @@ impl HoursRecord {
         index: isize,
         rowid: isize,
         name: String,
+        user_id: u64,
         year: i32,
         month: u32,
         start: String,
@@ -173,6 +395,7 @@ impl HoursRecord {
             index,
             rowid,
             name,
+            user_id,
             year,
             month,
             start,
@@ -196,7 +419,7 @@ impl HoursRecord {
 
     pub fn calculate_hours(&self) -> Result<Hours> {
         let duration = self.end_dt()?.signed_duration_since(self.start_dt()?);
-        Ok((duration.num_seconds() as f64 / 3600.0).into())
+        Ok(Hours::from_seconds(duration.num_seconds()))
     }
     pub fn original_hours(&self) -> String {
         if let Ok(h) = self.calculate_hours() {
@@ -206,28 +429,62 @@ impl HoursRecord {
         }
     }
     pub fn hours(&self) -> String {
-        if let Ok(h) = self.hours.parse::<f64>() {
-            format!("{}", Hours(h))
+        if let Ok(h) = Hours::from_decimal_str(&self.hours) {
+            format!("{}", h)
         } else {
             if let Ok(h) = self.calculate_hours() {
-                if h.0 > 0.0 && h.0*60.0*60.0<MAX_SECONDS as f64{
+                if h.seconds() > 0 {
                     format!("{}", h)
                 } else {
                     "unfinished".to_string()
                 }
+            } else if let Ok(h) = self.running_hours() {
+                format!("{} ...", h)
             } else {
                 "?".to_string()
             }
         }
     }
 
+    /// Hours of the override, the finished record or, while the record is open, so far.
+    /// Like [HoursRecord::hours], a record lasting longer than a shift counts no hours
+    /// until they are entered, see [crate::health::Problem::TooLong].
+    /// The hours override, else the time between start and end, else the running time.
+    /// Records longer than a shift count in full until an admin enters the hours; they are
+    /// listed by [crate::health] so that they are not missed.
     pub fn hours_worked(&self) -> Hours {
-        if let Ok(h) = self.hours.parse::<f64>() {
-            h.into()
+        if let Ok(h) = Hours::from_decimal_str(&self.hours) {
+            h
         } else {
-            self.calculate_hours().unwrap_or(Hours::default())
+            match self.calculate_hours() {
+                Ok(h) if h.seconds() > 0 => h,
+                Ok(_) => Hours::default(),
+                Err(_) => self.running_hours().unwrap_or_default(),
+            }
         }
     }
+    /// True if the record has ended more than a shift after its start and has no hours override.
+    pub fn is_too_long(&self) -> bool {
+        self.hours.trim().is_empty()
+            && self
+                .calculate_hours()
+                .map(|h| h.seconds() >= MAX_SECONDS)
+                .unwrap_or(false)
+    }
+
+    /// Time since the start of an open record; fails for ended or stale records.
+    pub fn running_hours(&self) -> Result<Hours> {
+        if !self.end.is_empty() {
+            return Err(anyhow!("Record {} has ended", self.index));
+        }
+        let duration = Local::now()
+            .naive_local()
+            .signed_duration_since(self.start_dt()?);
+        if duration.num_seconds() < 0 || duration.num_seconds() >= MAX_SECONDS {
+            return Err(anyhow!("Record {} is not running", self.index));
+        }
+        Ok(Hours::from_seconds(duration.num_seconds()))
+    }
 
     pub fn date(&self) -> String {
         if let Ok(d) = self.start_dt() {
@@ -287,6 +544,9 @@ impl HoursRecord {
 pub struct HoursDataFrame {
     pub schema: Schema,
     pub data: Vec<HoursRecord>,
+    /// Index of the next new record. It never decreases, so that record indices are not reused.
+    #[serde(default)]
+    pub next_index: isize,
 }
 
 impl HoursDataFrame {
@@ -294,30 +554,91 @@ impl HoursDataFrame {
         HoursDataFrame {
             schema: Schema::default(),
             data: Vec::new(),
+            next_index: 0,
+        }
+    }
+    /// Takes a new unique record index.
+    pub fn new_index(&mut self) -> isize {
+        let max = self.data.iter().map(|r| r.index + 1).max().unwrap_or(0);
+        let index = self.next_index.max(max);
+        self.next_index = index + 1;
+        index
+    }
+    /// Notes an index used by a record, so that it is not handed out again.
+    pub fn use_index(&mut self, index: isize) {
+        self.next_index = self.next_index.max(index + 1);
+    }
+    pub fn record(&self, index: isize) -> Option<&HoursRecord> {
+        self.data.iter().find(|r| r.index == index)
+    }
+    pub fn record_mut(&mut self, index: isize) -> Option<&mut HoursRecord> {
+        self.data.iter_mut().find(|r| r.index == index)
+    }
+    /// Gives every record but the first with the same index a new index.
+    /// Returns the number of records that got a new index.
+    pub fn repair_indices(&mut self) -> usize {
+        let mut seen = std::collections::HashSet::new();
+        let mut duplicates = Vec::new();
+        for (i, record) in self.data.iter().enumerate() {
+            if !seen.insert(record.index) {
+                duplicates.push(i);
+            }
+        }
+        for i in duplicates.iter() {
+            let index = self.new_index();
+            self.data[*i].index = index;
+            self.data[*i].rowid = index;
         }
+        let max = self.data.iter().map(|r| r.index + 1).max().unwrap_or(0);
+        self.next_index = self.next_index.max(max);
+        duplicates.len()
     }
-    pub fn for_period(&self, name: &str, period: &Period) -> HoursDataFrame {
+    pub fn for_period(&self, user_id: u64, period: &Period) -> HoursDataFrame {
         let mut df = HoursDataFrame::new();
         df.schema = self.schema.clone();
         df.data = self
             .data
             .iter()
-            .filter(|r| r.period() == *period && r.name == name)
+            .filter(|r| r.period() == *period && r.user_id == user_id)
             .cloned()
             .collect();
         df
     }
-    pub fn hours_for_period(&self, name: &str, period: &Period) -> Hours {
+    pub fn hours_for_period(&self, user_id: u64, period: &Period) -> Hours {
         self.data
             .iter()
-            .filter(|r| r.period() == *period && r.name == name)
+            .filter(|r| r.period() == *period && r.user_id == user_id)
             .map(|r| r.hours_worked())
             .sum()
     }
-    pub fn status_for_period(&self, name: &str, period: &Period) -> String {
+    /// Hours of the records started on `date`, including a running record.
+    pub fn hours_for_day(&self, user_id: u64, date: chrono::NaiveDate) -> Hours {
         self.data
             .iter()
-            .filter(|r| r.period() == *period && r.name == name)
+            .filter(|r| r.user_id == user_id)
+            .filter(|r| r.start_dt().map(|s| s.date() == date).unwrap_or(false))
+            .map(|r| r.hours_worked())
+            .sum()
+    }
+    /// Hours of the user for each day of the period, starting with the first.
+    pub fn hours_by_day(&self, user_id: u64, period: &Period) -> Vec<Hours> {
+        let mut days = vec![Hours::default(); period.days() as usize];
+        for record in self.data.iter() {
+            if record.user_id != user_id || record.period() != *period {
+                continue;
+            }
+            if let Ok(start) = record.start_dt() {
+                if let Some(hours) = days.get_mut(start.day() as usize - 1) {
+                    *hours = *hours + record.hours_worked();
+                }
+            }
+        }
+        days
+    }
+    pub fn status_for_period(&self, user_id: u64, period: &Period) -> String {
+        self.data
+            .iter()
+            .filter(|r| r.period() == *period && r.user_id == user_id)
             .last()
             .map(|r| r.worked())
             .unwrap_or(" - ".to_string())
@@ -342,64 +663,592 @@ impl HoursDataFrame {
     }
 }
 
+/// New ids given by [HoursData::merge_external] to users and records that were created
+/// concurrently with the same id or index, by old id or index.
+#[derive(Debug, Default)]
+struct MergeIds {
+    users: HashMap<u64, u64>,
+    records: HashMap<isize, isize>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
 pub struct HoursData {
     pub dataframe: HoursDataFrame,
-    pub names: Vec<String>,
+    pub users: Users,
+    #[serde(skip)]
+    pub storage: Option<Rc<dyn Storage>>,
+    /// Data folder the data was loaded from.
+    #[serde(skip)]
+    pub folder: PathBuf,
+    /// Set when loading failed; the data is then empty and read-only.
+    #[serde(skip)]
+    pub load_error: Option<String>,
+    /// Set when a save was refused because another process changed the data;
+    /// cleared by [HoursData::reload] or [HoursData::merge_external].
+    #[serde(skip)]
+    pub conflict: Option<String>,
+    /// Changes applied to this data that were not stored because of the conflict,
+    /// replayed by [HoursData::merge_external].
+    #[serde(skip)]
+    pub unsaved: Vec<Event>,
 }
 
 impl HoursData {
-    pub fn folder() -> PathBuf {
-        dirs::home_dir().unwrap().join("Hours")
+    pub fn load(settings: &Settings) -> Result<HoursData> {
+        Self::load_from(storage::open(settings)?)
     }
-    pub fn create_if_not_exists() -> Result<()> {
-        let path = Self::folder();
-        if !path.is_dir() {
-            std::fs::create_dir_all(&path)?;
+    /// Loads the data; records sharing an index, which older versions could create,
+    /// get new indices and the repaired data is saved.
+    pub fn load_from(storage: Rc<dyn Storage>) -> Result<HoursData> {
+        let mut hours_data = storage.load()?;
+        hours_data.folder = storage.folder();
+        hours_data.storage = Some(storage);
+        let repaired = hours_data.dataframe.repair_indices();
+        if repaired > 0 {
+            println!("Repairing {} records with duplicate index", repaired);
+            hours_data.save()?;
         }
-        let dataframe_path = (&path).join("hours_dataframe.json");
-        if !dataframe_path.exists() {
-            let hours_data = HoursData::default();
-            hours_data.save_to(&path)?;
+        Ok(hours_data)
+    }
+    /// Loads the data or, if that fails, returns an empty read-only instance
+    /// carrying the error, so that nothing overwrites the data that failed to load.
+    pub fn load_or_safe_mode(settings: &Settings) -> HoursData {
+        Self::load(settings).unwrap_or_else(|e| {
+            println!("Error loading data: {}", e);
+            Self::safe_mode(settings, e)
+        })
+    }
+    pub fn safe_mode(settings: &Settings, error: anyhow::Error) -> HoursData {
+        let message = match recovery::preserve_broken(&settings.data_folder) {
+            Ok(path) => format!("{} (a copy of the data was kept in {:?})", error, path),
+            Err(e) => format!("{} (the data could not be copied: {})", error, e),
+        };
+        HoursData {
+            folder: settings.data_folder.clone(),
+            load_error: Some(message),
+            ..HoursData::default()
         }
-        Ok(())
     }
-    pub fn load() -> Result<HoursData> {
-        Self::create_if_not_exists()?;
-        HoursData::from_store(Self::folder())
+    /// Empty read-only data for when the settings could not be read; no data folder is touched.
+    pub fn without_settings(error: &str) -> HoursData {
+        HoursData {
+            load_error: Some(format!("The settings could not be read: {}", error)),
+            ..HoursData::default()
+        }
+    }
+    pub fn is_read_only(&self) -> bool {
+        self.load_error.is_some()
+    }
+    fn check_writable(&self) -> Result<()> {
+        if let Some(e) = &self.load_error {
+            Err(anyhow!("Data is read-only because it failed to load: {}", e))
+        } else {
+            Ok(())
+        }
     }
     pub fn from_store<P: AsRef<Path>>(path: P) -> Result<HoursData> {
         println!("Load from {:?}", path.as_ref());
+        migration::migrate(path.as_ref())?;
 
-        let file = File::open(path.as_ref().join("hours_dataframe.json"))?;
+        let file = File::open(path.as_ref().join(DATAFRAME_FILE))?;
         //        let mut buf_reader = BufReader::new(file);
         let dataframe: HoursDataFrame = serde_json::from_reader(file)?;
 
-        let file = File::open(path.as_ref().join("hours_names.json"))?;
+        let file = File::open(path.as_ref().join(USERS_FILE))?;
         //        let mut buf_reader = BufReader::new(file);
-        let names: Names = serde_json::from_reader(file)?;
+        let users: Users = serde_json::from_reader(file)?;
 
-        Ok(HoursData { dataframe, names })
+        Ok(HoursData {
+            dataframe,
+            users,
+            ..HoursData::default()
+        })
     }
 
     pub fn save_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        std::fs::write(
-            path.as_ref().join("hours_dataframe.json"),
+        atomic_write(
+            path.as_ref().join(DATAFRAME_FILE),
             serde_json::to_string_pretty(&self.dataframe).unwrap(),
         )?;
-        std::fs::write(
-            path.as_ref().join("hours_names.json"),
-            serde_json::to_string_pretty(&self.names).unwrap(),
+        atomic_write(
+            path.as_ref().join(USERS_FILE),
+            serde_json::to_string_pretty(&self.users).unwrap(),
+        )?;
+        atomic_write(
+            path.as_ref().join(META_FILE),
+            serde_json::to_string_pretty(&Meta::default()).unwrap(),
         )?;
         Ok(())
     }
-    pub fn save(&self) -> Result<()> {
-        let path = Self::folder();
-        println!("Saving to {:?}", path);
-        self.save_to(path)
+    pub fn save(&mut self) -> Result<()> {
+        self.check_writable()?;
+        let result = if let Some(storage) = &self.storage {
+            storage.save(self)
+        } else {
+            Err(anyhow!("Data has no storage to save to"))
+        };
+        self.note_conflict(result)
+    }
+    /// Stores a change that has already been applied to this data.
+    pub fn commit(&mut self, event: Event) -> Result<()> {
+        self.check_writable()?;
+        let result = if let Some(storage) = &self.storage {
+            storage.record(self, &event)
+        } else {
+            return self.save();
+        };
+        if let Err(e) = &result {
+            if e.downcast_ref::<ExternalModification>().is_some() {
+                self.unsaved.push(event);
+            }
+        }
+        self.note_conflict(result)
+    }
+    /// Sets the hours override of a record from user input, see [Hours::from_str].
+    pub fn set_hours(&mut self, access: &Access, index: isize, input: &str) -> Result<()> {
+        let hours = Hours::normalize(input)?;
+        let user_id = self
+            .dataframe
+            .record(index)
+            .map(|r| r.user_id)
+            .ok_or_else(|| anyhow!("No record {}", index))?;
+        self.require_edit_user(access, user_id)?;
+        self.dataframe.record_mut(index).unwrap().hours = hours;
+        self.save_record(access, index)
+    }
+    /// Stores the record with `index` after it has been edited.
+    pub fn save_record(&mut self, access: &Access, index: isize) -> Result<()> {
+        let record = self
+            .dataframe
+            .record(index)
+            .cloned()
+            .ok_or_else(|| anyhow!("No record {}", index))?;
+        self.require_edit_user(access, record.user_id)?;
+        self.commit(Event::Edited { record })
+    }
+    /// Fails unless `access` may change the user and its records;
+    /// records of unknown users can only be changed by admins.
+    pub fn require_edit_user(&self, access: &Access, id: u64) -> Result<()> {
+        match self.user(id) {
+            Some(user) => access.require_edit_user(user),
+            None => access.require_admin(),
+        }
+    }
+    pub fn can_edit_user(&self, access: &Access, id: u64) -> bool {
+        self.require_edit_user(access, id).is_ok()
+    }
+    pub fn user(&self, id: u64) -> Option<&UserProfile> {
+        self.users.iter().find(|u| u.id == id)
+    }
+    /// Name of the user, with the employee number or team added if another user has the same name.
+    pub fn display_name(&self, id: u64) -> String {
+        match self.user(id) {
+            Some(user) => {
+                let same_name = self.users.iter().filter(|u| u.name == user.name).count();
+                if same_name > 1 && !user.employee_number.is_empty() {
+                    format!("{} ({})", user.name, user.employee_number)
+                } else if same_name > 1 && !user.team.is_empty() {
+                    format!("{} ({})", user.name, user.team)
+                } else {
+                    user.name.clone()
+                }
+            }
+            None => format!("Unknown user {}", id),
+        }
+    }
+    /// Id for a new user; ids still referenced by records are never reused.
+    fn next_user_id(&self) -> u64 {
+        let max_user = self.users.iter().map(|u| u.id).max().unwrap_or(0);
+        let max_record = self.dataframe.data.iter().map(|r| r.user_id).max().unwrap_or(0);
+        max_user.max(max_record) + 1
+    }
+    /// Adds a user; users added by a manager belong to the manager's team.
+    pub fn add_user(&mut self, access: &Access, name: &str) -> Result<u64> {
+        self.check_writable()?;
+        access.require_edit()?;
+        let mut user = UserProfile::new(self.next_user_id(), name);
+        if !access.is_admin() {
+            user.team = access.team.clone();
+        }
+        let id = user.id;
+        self.users.push(user.clone());
+        self.commit(Event::UserAdded { user })?;
+        Ok(id)
+    }
+    /// Replaces the profile with the same id. The name is changed with [HoursData::rename_user].
+    pub fn update_user(&mut self, access: &Access, user: UserProfile) -> Result<()> {
+        self.check_writable()?;
+        self.require_edit_user(access, user.id)?;
+        access.require_edit_user(&user)?;
+        let existing = self
+            .users
+            .iter_mut()
+            .find(|u| u.id == user.id)
+            .ok_or_else(|| anyhow!("No user {}", user.id))?;
+        if existing.name != user.name {
+            return Err(anyhow!("Use rename to change the name of {}", existing.name));
+        }
+        *existing = user.clone();
+        self.commit(Event::UserUpdated { user })
+    }
+    /// Fails if another user already has the name (ignoring case).
+    fn check_name_free(&self, id: u64, name: &str) -> Result<()> {
+        if self
+            .users
+            .iter()
+            .any(|u| u.id != id && u.name.to_lowercase() == name.to_lowercase())
+        {
+            return Err(anyhow!("There is already a user called {}", name));
+        }
+        Ok(())
+    }
+    /// Saves an edited profile, renaming the user first if the name changed.
+    /// Both changes are checked before either is stored, so that a rejected profile
+    /// does not leave the user renamed.
+    pub fn save_profile(&mut self, access: &Access, user: UserProfile) -> Result<()> {
+        self.check_writable()?;
+        self.require_edit_user(access, user.id)?;
+        access.require_edit_user(&user)?;
+        let name = user.name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("The name can not be empty"));
+        }
+        self.check_name_free(user.id, name)?;
+        let user = UserProfile {
+            name: name.to_string(),
+            ..user
+        };
+        self.rename_user(access, user.id, &user.name)?;
+        self.update_user(access, user)
+    }
+    /// Renames the user and all of its records in one event.
+    /// Fails if the name is empty or another user already has it (ignoring case).
+    pub fn rename_user(&mut self, access: &Access, id: u64, name: &str) -> Result<()> {
+        self.check_writable()?;
+        self.require_edit_user(access, id)?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(anyhow!("The name can not be empty"));
+        }
+        let from = self
+            .user(id)
+            .map(|u| u.name.clone())
+            .ok_or_else(|| anyhow!("No user {}", id))?;
+        if from == name {
+            return Ok(());
+        }
+        self.check_name_free(id, name)?;
+        let event = Event::UserRenamed {
+            id,
+            from,
+            to: name.to_string(),
+        };
+        event.apply(self);
+        self.commit(event)
+    }
+    /// Archives or reactivates the user.
+    pub fn set_archived(&mut self, access: &Access, id: u64, archived: bool) -> Result<()> {
+        let mut user = self
+            .user(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("No user {}", id))?;
+        user.archived = archived;
+        self.update_user(access, user)
+    }
+    pub fn has_pin(&self, id: u64) -> bool {
+        self.user(id).map(|u| !u.pin_hash.is_empty()).unwrap_or(false)
+    }
+    /// True if the PIN of the user has to be entered to start or end:
+    /// always if the user has one, for all users if PINs are `required`.
+    pub fn needs_pin(&self, id: u64, required: bool) -> bool {
+        required || self.has_pin(id)
+    }
+    pub fn verify_pin(&self, id: u64, pin: &str) -> bool {
+        self.user(id)
+            .map(|u| auth::verify_secret(pin, &u.pin_hash))
+            .unwrap_or(false)
+    }
+    pub fn set_pin(&mut self, access: &Access, id: u64, pin: &str) -> Result<()> {
+        auth::validate_pin(pin)?;
+        let mut user = self
+            .user(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("No user {}", id))?;
+        user.pin_hash = auth::hash_secret(pin)?;
+        self.update_user(access, user)
+    }
+    /// Removes the PIN, e.g. when the user forgot it.
+    pub fn reset_pin(&mut self, access: &Access, id: u64) -> Result<()> {
+        let mut user = self
+            .user(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("No user {}", id))?;
+        user.pin_hash = String::new();
+        self.update_user(access, user)
+    }
+    /// Number of records linked to the user.
+    pub fn record_count(&self, id: u64) -> usize {
+        self.dataframe.data.iter().filter(|r| r.user_id == id).count()
+    }
+    /// Removes the user at position `i`; only users without records can be removed,
+    /// others have to be archived.
+    pub fn remove_user(&mut self, access: &Access, i: usize) -> Result<()> {
+        self.check_writable()?;
+        let user = self
+            .users
+            .get(i)
+            .ok_or_else(|| anyhow!("No user {}", i))?;
+        access.require_edit_user(user)?;
+        if self.record_count(user.id) > 0 {
+            return Err(anyhow!("{} has records, archive the user instead", user.name));
+        }
+        let user = self.users.remove(i);
+        self.commit(Event::UserRemoved { id: user.id })
+    }
+    pub fn orphans(&self) -> Vec<Orphan> {
+        let mut orphans: Vec<Orphan> = Vec::new();
+        for record in self.dataframe.data.iter() {
+            if self.user(record.user_id).is_some() {
+                continue;
+            }
+            let date = record.date();
+            match orphans
+                .iter_mut()
+                .find(|o| o.user_id == record.user_id && o.name == record.name)
+            {
+                Some(orphan) => {
+                    orphan.records += 1;
+                    if date < orphan.first_date {
+                        orphan.first_date = date;
+                    } else if date > orphan.last_date {
+                        orphan.last_date = date;
+                    }
+                }
+                None => orphans.push(Orphan {
+                    user_id: record.user_id,
+                    name: record.name.clone(),
+                    records: 1,
+                    first_date: date.clone(),
+                    last_date: date,
+                }),
+            }
+        }
+        orphans
+    }
+    /// Links the records of the orphan to an existing user, merging them into its records.
+    pub fn attach_orphan(&mut self, access: &Access, orphan: &Orphan, user_id: u64) -> Result<()> {
+        self.check_writable()?;
+        access.require_admin()?;
+        let name = self
+            .user(user_id)
+            .map(|u| u.name.clone())
+            .ok_or_else(|| anyhow!("No user {}", user_id))?;
+        let event = Event::RecordsReassigned {
+            from_user_id: orphan.user_id,
+            from_name: orphan.name.clone(),
+            user_id,
+            name,
+        };
+        event.apply(self);
+        self.commit(event)
+    }
+    /// Creates a user for the orphan. A removed user is recreated with its old id,
+    /// records without id are linked to a new user.
+    pub fn restore_orphan(&mut self, access: &Access, orphan: &Orphan) -> Result<u64> {
+        self.check_writable()?;
+        access.require_admin()?;
+        if orphan.user_id == 0 {
+            let id = self.add_user(access, &orphan.name)?;
+            self.attach_orphan(access, orphan, id)?;
+            Ok(id)
+        } else {
+            let user = UserProfile::new(orphan.user_id, &orphan.name);
+            self.users.push(user.clone());
+            self.commit(Event::UserAdded { user })?;
+            Ok(orphan.user_id)
+        }
+    }
+    /// Applies the fix of an issue found by [health::check].
+    pub fn fix_issue(&mut self, access: &Access, issue: &Issue) -> Result<()> {
+        self.check_writable()?;
+        access.require_admin()?;
+        let record = issue.fix(&self.dataframe)?;
+        if let Some(r) = self.dataframe.record_mut(record.index) {
+            *r = record.clone();
+        }
+        self.commit(Event::Edited { record })
+    }
+    /// Fixes all issues that have a fix, one by one, as a fix may also solve other issues.
+    /// Returns the number of fixed issues.
+    pub fn fix_all(&mut self, access: &Access) -> Result<usize> {
+        let mut fixed = 0;
+        for issue in health::check(&self.dataframe) {
+            if issue.fix_label().is_some() && health::check(&self.dataframe).contains(&issue) {
+                self.fix_issue(access, &issue)?;
+                fixed += 1;
+            }
+        }
+        Ok(fixed)
+    }
+    /// Moves the user at position `i` to position `to`; the order is shared by all teams.
+    pub fn move_user(&mut self, access: &Access, i: usize, to: usize) -> Result<()> {
+        self.check_writable()?;
+        access.require_admin()?;
+        if i >= self.users.len() || to >= self.users.len() {
+            return Err(anyhow!("Can not move user {} to {}", i, to));
+        }
+        let user = self.users.remove(i);
+        self.users.insert(to, user);
+        self.commit(Event::UsersReordered {
+            ids: self.users.iter().map(|u| u.id).collect(),
+        })
+    }
+    fn note_conflict(&mut self, result: Result<()>) -> Result<()> {
+        if let Err(e) = &result {
+            if e.downcast_ref::<ExternalModification>().is_some() {
+                self.conflict = Some(e.to_string());
+            }
+        }
+        result
+    }
+
+    /// Replaces the data by the current content of the store, dropping unsaved changes.
+    pub fn reload(&mut self) -> Result<()> {
+        let storage = self
+            .storage
+            .clone()
+            .ok_or_else(|| anyhow!("Data has no storage to reload from"))?;
+        *self = Self::load_from(storage)?;
+        Ok(())
+    }
+
+    /// Loads the data changed by another process, applies the changes of [HoursData::unsaved]
+    /// on top of it and stores them. Everything else is taken from the other process,
+    /// so that none of its changes are undone.
+    pub fn merge_external(&mut self) -> Result<()> {
+        let storage = self
+            .storage
+            .clone()
+            .ok_or_else(|| anyhow!("Data has no storage to merge with"))?;
+        let mut merged = Self::load_from(storage)?;
+        let mut ids = MergeIds::default();
+        for event in self.unsaved.iter() {
+            let event = merged.replay(event.clone(), &mut ids);
+            event.apply(&mut merged);
+            merged.commit(event)?;
+        }
+        *self = merged;
+        Ok(())
     }
 
-    pub fn start(&mut self, name: &str) -> Result<()> {
+    /// Adapts an event of [HoursData::unsaved] to this data before it is applied:
+    /// records and users created concurrently with the same index or id get a new one,
+    /// and a record that has been ended here keeps its end.
+    fn replay(&mut self, event: Event, ids: &mut MergeIds) -> Event {
+        let user_id = |ids: &MergeIds, id: u64| ids.users.get(&id).copied().unwrap_or(id);
+        match event {
+            Event::Started { mut record } => {
+                record.user_id = user_id(ids, record.user_id);
+                if self.dataframe.record(record.index).is_some() {
+                    let index = self.dataframe.new_index();
+                    ids.records.insert(record.index, index);
+                    record.index = index;
+                    record.rowid = index;
+                }
+                Event::Started { record }
+            }
+            Event::Ended { record } => Event::Ended {
+                record: self.replay_record(record, ids, true),
+            },
+            Event::Edited { record } => Event::Edited {
+                record: self.replay_record(record, ids, false),
+            },
+            Event::UserAdded { mut user } => {
+                if self.user(user.id).is_some() {
+                    let id = self.next_user_id().max(user.id + 1);
+                    ids.users.insert(user.id, id);
+                    user.id = id;
+                }
+                Event::UserAdded { user }
+            }
+            Event::UserUpdated { mut user } => {
+                user.id = user_id(ids, user.id);
+                Event::UserUpdated { user }
+            }
+            Event::UserRenamed { id, from, to } => Event::UserRenamed {
+                id: user_id(ids, id),
+                from,
+                to,
+            },
+            Event::UserRemoved { id } => Event::UserRemoved {
+                id: user_id(ids, id),
+            },
+            Event::RecordsReassigned {
+                from_user_id,
+                from_name,
+                user_id: to_user_id,
+                name,
+            } => Event::RecordsReassigned {
+                from_user_id: user_id(ids, from_user_id),
+                from_name,
+                user_id: user_id(ids, to_user_id),
+                name,
+            },
+            Event::UsersReordered { ids: order } => Event::UsersReordered {
+                ids: order.into_iter().map(|id| user_id(ids, id)).collect(),
+            },
+        }
+    }
+
+    /// The record of an `Ended` or `Edited` event as it is to be stored here.
+    /// Ending only sets the end of a record that is still open;
+    /// an edit of a record that is open in the event but ended here keeps the end.
+    fn replay_record(&self, mut record: HoursRecord, ids: &MergeIds, ended: bool) -> HoursRecord {
+        record.user_id = ids.users.get(&record.user_id).copied().unwrap_or(record.user_id);
+        if let Some(index) = ids.records.get(&record.index) {
+            record.index = *index;
+            record.rowid = *index;
+        }
+        match self.dataframe.record(record.index) {
+            Some(existing) if ended => {
+                let mut existing = existing.clone();
+                if existing.end.is_empty() {
+                    existing.end = record.end;
+                }
+                existing
+            }
+            Some(existing) => {
+                if record.end.is_empty() {
+                    record.end = existing.end.clone();
+                }
+                record
+            }
+            None => record,
+        }
+    }
+
+    /// Starts a new record; fails if the user is already working.
+    pub fn start(&mut self, user_id: u64) -> Result<()> {
+        self.check_writable()?;
+        match self.clock_state(user_id) {
+            ClockState::Idle => {}
+            ClockState::Working { index } | ClockState::Stale { index } => {
+                let record = self.dataframe.record(index).cloned().unwrap_or_default();
+                return Err(anyhow!(
+                    "{} already started on {} at {}",
+                    self.display_name(user_id),
+                    record.date(),
+                    record.start_time()
+                ));
+            }
+        }
+        let user = self
+            .user(user_id)
+            .ok_or_else(|| anyhow!("No user {}", user_id))?;
+        if user.archived {
+            return Err(anyhow!("{} is archived and can not start", user.name));
+        }
+        let name = user.name.clone();
         let now = Local::now();
         let year = now.year();
         let month = now.month();
@@ -413,24 +1262,44 @@ impl HoursData {
         );
         let end = "".to_string();
         let hours = "".to_string();
-        let index = self.dataframe.data.len() as isize;
+        let index = self.dataframe.new_index();
         let rowid = index;
         let record = HoursRecord::new(
             index,
             rowid,
-            name.to_string(),
+            name,
+            user_id,
             year,
             month,
             start,
             end,
             hours,
         );
-        self.dataframe.data.push(record);
-        self.save()?;
+        self.dataframe.data.push(record.clone());
+        self.commit(Event::Started { record })?;
         Ok(())
     }
 
-    pub fn end(&mut self, name: &str) -> Result<()> {
+    /// Ends the open record of the user; fails if the user has not started.
+    /// A stale record is not ended now, as that would count all the time since its start;
+    /// it has to be closed with [HoursData::close_stale].
+    pub fn end(&mut self, user_id: u64) -> Result<()> {
+        self.check_writable()?;
+        let index = match self.clock_state(user_id) {
+            ClockState::Working { index } => index,
+            ClockState::Stale { index } => {
+                let record = self.dataframe.record(index).cloned().unwrap_or_default();
+                return Err(anyhow!(
+                    "{} started on {} at {} and did not end, close the record without hours",
+                    self.display_name(user_id),
+                    record.date(),
+                    record.start_time()
+                ));
+            }
+            ClockState::Idle => {
+                return Err(anyhow!("{} has not started", self.display_name(user_id)))
+            }
+        };
         let now = Local::now();
         let year = now.year();
         let month = now.month();
@@ -442,17 +1311,7 @@ impl HoursData {
             "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
             year, month, day, hour, minute, second
         );
-        let _index = self.dataframe.data.len() as isize;
-        let mut rowid = -1;
-        for (i, record) in self.dataframe.data.iter().enumerate() {
-            if record.name == name && record.end == "" {
-                rowid = i as isize;
-            }
-        }
-        if rowid == -1 {
-            return Err(anyhow!("No start record found for {}", name));
-        }
-        let mut record = self.dataframe.data.get_mut(rowid as usize).unwrap();
+        let record = self.dataframe.record_mut(index).unwrap();
         record.end = end;
         /*
         let start = chrono::NaiveDateTime::parse_from_str(&record.start, "%Y-%m-%d %H:%M:%S")?;
@@ -462,19 +1321,367 @@ impl HoursData {
         record.hours = hours;
         */
         record.hours = "".to_string();
-        self.save()?;
+        let record = record.clone();
+        self.commit(Event::Ended { record })?;
         Ok(())
     }
 
-    pub fn is_started(&self, name: &str) -> bool {
-        let mut started = false;
-        for record in self.dataframe.data.iter() {
-            if record.name == name {
-                started = record.end == "";
+    /// Closes a stale record with zero hours at its start time.
+    /// The hours of the stale record have to be entered by someone who knows them.
+    pub fn close_stale(&mut self, user_id: u64) -> Result<()> {
+        self.check_writable()?;
+        let index = match self.clock_state(user_id) {
+            ClockState::Stale { index } => index,
+            _ => return Err(anyhow!("{} has no stale record", self.display_name(user_id))),
+        };
+        let record = self.dataframe.record_mut(index).unwrap();
+        record.end = record.start.clone();
+        let record = record.clone();
+        self.commit(Event::Ended { record })
+    }
+
+    /// Closes a stale record with [HoursData::close_stale] and starts a new record.
+    pub fn close_stale_and_start(&mut self, user_id: u64) -> Result<()> {
+        self.close_stale(user_id)?;
+        self.start(user_id)
+    }
+
+    /// State of the latest record without end; older records without end are listed by [crate::health].
+    pub fn clock_state(&self, user_id: u64) -> ClockState {
+        let open = self
+            .dataframe
+            .data
+            .iter()
+            .filter(|r| r.user_id == user_id && r.end.is_empty())
+            .max_by(|a, b| a.start.cmp(&b.start));
+        match open {
+            None => ClockState::Idle,
+            Some(record) => {
+                let stale = record
+                    .start_dt()
+                    .map(|start| {
+                        Local::now()
+                            .naive_local()
+                            .signed_duration_since(start)
+                            .num_seconds()
+                            >= MAX_SECONDS
+                    })
+                    .unwrap_or(true);
+                if stale {
+                    ClockState::Stale {
+                        index: record.index,
+                    }
+                } else {
+                    ClockState::Working {
+                        index: record.index,
+                    }
+                }
             }
         }
-        started
+    }
+
+    pub fn is_started(&self, user_id: u64) -> bool {
+        self.clock_state(user_id) != ClockState::Idle
     }
 }
 
-pub type Names = Vec<String>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Role;
+    use crate::test_util::{admin, data_folder};
+
+    /// Data folder with the users Anna (1), Bob (2) and Carl (3); Anna is working.
+    fn folder() -> (tempfile::TempDir, Settings) {
+        let (dir, settings) = data_folder();
+        let mut data = HoursData::load(&settings).unwrap();
+        for name in ["Anna", "Bob", "Carl"] {
+            data.add_user(&admin(), name).unwrap();
+        }
+        data.start(1).unwrap();
+        (dir, settings)
+    }
+
+    #[test]
+    fn merge_keeps_external_end() {
+        let (_dir, settings) = folder();
+        let mut a = HoursData::load(&settings).unwrap();
+        let mut b = HoursData::load(&settings).unwrap();
+        b.end(1).unwrap();
+        b.set_pin(&admin(), 2, "1234").unwrap();
+        b.set_archived(&admin(), 3, true).unwrap();
+
+        assert!(a.start(2).is_err());
+        assert!(a.conflict.is_some());
+        assert_eq!(a.unsaved.len(), 1);
+        a.merge_external().unwrap();
+        assert!(a.conflict.is_none() && a.unsaved.is_empty());
+        for data in [&a, &HoursData::load(&settings).unwrap()] {
+            assert_eq!(data.clock_state(1), ClockState::Idle);
+            assert!(matches!(data.clock_state(2), ClockState::Working { .. }));
+            assert!(data.has_pin(2));
+            assert!(data.user(3).unwrap().archived);
+            assert_eq!(data.dataframe.data.len(), 2);
+        }
+    }
+
+    #[test]
+    fn merge_keeps_end_of_edited_record() {
+        let (_dir, settings) = folder();
+        let mut a = HoursData::load(&settings).unwrap();
+        let mut b = HoursData::load(&settings).unwrap();
+        b.end(1).unwrap();
+        let index = a.dataframe.data[0].index;
+        assert!(a.set_hours(&admin(), index, "7:30").is_err());
+        a.merge_external().unwrap();
+        let record = HoursData::load(&settings).unwrap().dataframe.data[0].clone();
+        assert_eq!(record.hours, "7.5");
+        assert!(!record.end.is_empty());
+    }
+
+    #[test]
+    fn merge_gives_concurrent_records_new_indices() {
+        let (_dir, settings) = folder();
+        let mut a = HoursData::load(&settings).unwrap();
+        let mut b = HoursData::load(&settings).unwrap();
+        b.start(2).unwrap();
+        assert!(a.start(3).is_err());
+        assert!(a.end(3).is_err());
+        assert_eq!(a.unsaved.len(), 2);
+        a.merge_external().unwrap();
+        let data = HoursData::load(&settings).unwrap();
+        let mut indices = data.dataframe.data.iter().map(|r| r.index).collect::<Vec<_>>();
+        indices.sort();
+        indices.dedup();
+        assert_eq!(indices.len(), 3);
+        assert!(matches!(data.clock_state(2), ClockState::Working { .. }));
+        assert_eq!(data.clock_state(3), ClockState::Idle);
+        assert_eq!(data.dataframe.data.iter().filter(|r| r.user_id == 3).count(), 1);
+    }
+
+    #[test]
+    fn merge_keeps_external_rename() {
+        let (_dir, settings) = folder();
+        let mut a = HoursData::load(&settings).unwrap();
+        let mut b = HoursData::load(&settings).unwrap();
+        b.rename_user(&admin(), 3, "Charles").unwrap();
+        assert!(a.start(3).is_err());
+        a.merge_external().unwrap();
+        for data in [&a, &HoursData::load(&settings).unwrap()] {
+            let users = data.users.iter().map(|u| (u.id, u.name.as_str())).collect::<Vec<_>>();
+            assert_eq!(users, vec![(1, "Anna"), (2, "Bob"), (3, "Charles")]);
+            assert!(matches!(data.clock_state(3), ClockState::Working { .. }));
+            assert!(data.orphans().is_empty());
+        }
+    }
+
+    #[test]
+    fn merge_gives_concurrently_added_user_new_id() {
+        let (_dir, settings) = folder();
+        let mut a = HoursData::load(&settings).unwrap();
+        let mut b = HoursData::load(&settings).unwrap();
+        assert_eq!(b.add_user(&admin(), "Dora").unwrap(), 4);
+        assert!(a.add_user(&admin(), "Emil").is_err());
+        assert!(a.start(4).is_err());
+        a.rename_user(&admin(), 4, "Emilia").unwrap_err();
+        a.merge_external().unwrap();
+        let data = HoursData::load(&settings).unwrap();
+        let users = data.users.iter().map(|u| (u.id, u.name.as_str())).collect::<Vec<_>>();
+        assert_eq!(
+            users,
+            vec![(1, "Anna"), (2, "Bob"), (3, "Carl"), (4, "Dora"), (5, "Emilia")]
+        );
+        assert_eq!(data.clock_state(4), ClockState::Idle);
+        assert!(matches!(data.clock_state(5), ClockState::Working { .. }));
+        let record = data.dataframe.data.iter().find(|r| r.user_id == 5).unwrap();
+        assert_eq!(record.name, "Emilia");
+    }
+
+    #[test]
+    fn rejected_profile_is_not_partly_saved() {
+        let (_dir, settings) = folder();
+        let mut data = HoursData::load(&settings).unwrap();
+        let mut bob = data.user(2).cloned().unwrap();
+        bob.team = "A".to_string();
+        data.update_user(&admin(), bob.clone()).unwrap();
+        let manager = Access {
+            name: "manager".to_string(),
+            role: Role::Manager,
+            team: "A".to_string(),
+        };
+
+        let edited = UserProfile {
+            name: "Robert".to_string(),
+            team: "B".to_string(),
+            ..bob.clone()
+        };
+        assert!(data.save_profile(&manager, edited).is_err());
+        let edited = UserProfile {
+            name: "anna".to_string(),
+            weekly_hours: 20.0,
+            ..bob.clone()
+        };
+        assert!(data.save_profile(&manager, edited).is_err());
+        assert_eq!(data.user(2), Some(&bob));
+        assert_eq!(HoursData::load(&settings).unwrap().user(2), Some(&bob));
+
+        let edited = UserProfile {
+            name: " Robert ".to_string(),
+            weekly_hours: 20.0,
+            ..bob.clone()
+        };
+        data.save_profile(&manager, edited).unwrap();
+        let loaded = HoursData::load(&settings).unwrap();
+        let robert = loaded.user(2).unwrap();
+        assert_eq!((robert.name.as_str(), robert.weekly_hours), ("Robert", 20.0));
+    }
+
+    #[test]
+    fn archived_user_can_not_start() {
+        let (_dir, settings) = folder();
+        let mut data = HoursData::load(&settings).unwrap();
+        data.set_archived(&admin(), 2, true).unwrap();
+        assert!(data.start(2).is_err());
+        assert_eq!(data.clock_state(2), ClockState::Idle);
+        data.set_archived(&admin(), 2, false).unwrap();
+        data.start(2).unwrap();
+    }
+
+    #[test]
+    fn stale_record_is_closed_without_hours() {
+        let (_dir, settings) = folder();
+        let mut data = HoursData::load(&settings).unwrap();
+        let start = (Local::now() - chrono::Duration::days(3)).format("%Y-%m-%d %H:%M:%S");
+        data.dataframe.data[0].start = start.to_string();
+        let index = data.dataframe.data[0].index;
+        assert_eq!(data.clock_state(1), ClockState::Stale { index });
+        assert!(data.end(1).is_err());
+        data.close_stale(1).unwrap();
+        let record = data.dataframe.record(index).unwrap();
+        assert_eq!(record.end, record.start);
+        assert_eq!(record.hours_worked(), Hours::default());
+        assert_eq!(data.clock_state(1), ClockState::Idle);
+        let issues = health::check(&data.dataframe);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].index, index);
+        assert_eq!(issues[0].problem, health::Problem::ClosedWithoutHours);
+        assert_eq!(issues[0].fix_label(), None);
+    }
+
+    #[test]
+    fn too_long_record_counts_until_overridden() {
+        let mut record = HoursRecord {
+            start: "2023-04-03 08:00:00".to_string(),
+            end: "2023-04-06 08:00:00".to_string(),
+            ..HoursRecord::default()
+        };
+        assert!(record.is_too_long());
+        assert_eq!(record.hours_worked(), Hours::from_hours(72.0));
+        assert_eq!(record.hours(), format!("{}", Hours::from_hours(72.0)));
+        let mut dataframe = HoursDataFrame::new();
+        dataframe.data.push(record.clone());
+        let issues = health::check(&dataframe);
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].problem, health::Problem::TooLong);
+        assert_eq!(issues[0].fix_label(), None);
+
+        record.hours = "8".to_string();
+        assert!(!record.is_too_long());
+        assert_eq!(record.hours_worked(), Hours::from_hours(8.0));
+        dataframe.data[0] = record;
+        assert!(health::check(&dataframe).is_empty());
+    }
+
+    #[test]
+    fn hours_input() {
+        let minutes = |s: &str| s.parse::<Hours>().map(|h| h.minutes()).ok();
+        assert_eq!(minutes("7:30"), Some(450));
+        assert_eq!(minutes("7,5"), Some(450));
+        assert_eq!(minutes("7.5"), Some(450));
+        assert_eq!(minutes("8h 15m"), Some(495));
+        assert_eq!(minutes("8h"), Some(480));
+        assert_eq!(minutes("45m"), Some(45));
+        assert_eq!(minutes(" 45 min "), Some(45));
+        assert_eq!(minutes("24"), Some(24 * 60));
+        for invalid in ["7:60", "25", "-1", "m", "hm", "h", "", "abc", "7:", "NaN"] {
+            assert_eq!(minutes(invalid), None, "{}", invalid);
+        }
+        assert_eq!(Hours::normalize("7:30").unwrap(), "7.5");
+        assert_eq!(Hours::normalize(" ").unwrap(), "");
+    }
+
+    #[test]
+    fn stored_hours_outside_input_range() {
+        let record = HoursRecord {
+            start: "2023-04-03 08:00:00".to_string(),
+            end: "2023-04-03 09:00:00".to_string(),
+            hours: "-1.5".to_string(),
+            ..HoursRecord::default()
+        };
+        assert_eq!(record.hours_worked(), Hours::from_minutes(-90));
+        assert_eq!(record.hours(), "-01:30");
+        let mut dataframe = HoursDataFrame::new();
+        dataframe.data.push(record);
+        assert!(health::check(&dataframe).is_empty());
+    }
+
+    #[test]
+    fn typed_hours_are_stored_as_decimal_by_the_health_fix() {
+        let mut dataframe = HoursDataFrame::new();
+        for (index, hours) in [(0, "7:30"), (1, "abc")] {
+            dataframe.data.push(HoursRecord {
+                index,
+                user_id: index as u64,
+                start: "2023-04-03 08:00:00".to_string(),
+                end: "2023-04-03 16:00:00".to_string(),
+                hours: hours.to_string(),
+                ..HoursRecord::default()
+            });
+        }
+        let issues = health::check(&dataframe);
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].problem, health::Problem::HoursNotDecimal);
+        assert_eq!(issues[0].fix_label(), Some("Store hours as decimal"));
+        assert_eq!(issues[0].fix(&dataframe).unwrap().hours, "7.5");
+        assert_eq!(issues[1].problem, health::Problem::InvalidHours);
+        assert_eq!(issues[1].fix_label(), Some("Clear hours"));
+        assert_eq!(issues[1].fix(&dataframe).unwrap().hours, "");
+    }
+
+    #[test]
+    fn hours_rounding_and_format() {
+        assert_eq!(Hours::from_hours(7.9999).to_string(), "08:00");
+        assert_eq!(Hours::from_hours(7.5).to_string(), "07:30");
+        assert_eq!(Hours::from_minutes(-1).to_string(), "-00:01");
+        assert_eq!(Hours::from_hours(-1.5).to_string(), "-01:30");
+        assert_eq!(Hours::from_seconds(29).to_string(), "00:00");
+        for (seconds, minutes) in [(29, 0), (30, 1), (89, 1), (90, 2), (-29, 0), (-30, -1), (-90, -2)] {
+            assert_eq!(Hours::from_seconds(seconds).minutes(), minutes, "{}", seconds);
+        }
+    }
+
+    #[test]
+    fn hours_sum_is_exact() {
+        let tenth = Hours::from_decimal_str("0.1").unwrap();
+        let sum: Hours = std::iter::repeat_n(tenth, 10_000).sum();
+        assert_eq!(sum, Hours::from_hours(1000.0));
+        let seconds: Hours = (0..3600).map(|_| Hours::from_seconds(1)).sum();
+        assert_eq!(seconds, Hours::from_hours(1.0));
+        assert_eq!(sum - seconds + -sum, -Hours::from_hours(1.0));
+    }
+
+    #[test]
+    fn hours_serde() {
+        for (text, minutes) in [("\"7.5\"", 450), ("\"7,5\"", 450), ("\"-1.25\"", -75), ("8", 480), ("\"7:30\"", 450)] {
+            let hours: Hours = serde_json::from_str(text).unwrap();
+            assert_eq!(hours.minutes(), minutes, "{}", text);
+        }
+        let hours = Hours::from_seconds(7 * 3600 + 20 * 60 + 10);
+        let json = serde_json::to_string(&hours).unwrap();
+        assert_eq!(json, format!("\"{}\"", hours.to_decimal_string()));
+        let read: Hours = serde_json::from_str(&json).unwrap();
+        assert_eq!(read, Hours::from_minutes(440));
+        assert_eq!(serde_json::to_string(&Hours::from_minutes(-90)).unwrap(), "\"-1.5\"");
+    }
+}
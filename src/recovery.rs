@@ -0,0 +1,172 @@
+use crate::backup;
+use crate::journal::{JournalEntry, JOURNAL_FILE};
+use crate::migration::{self, DATAFRAME_FILE, NAMES_FILE, USERS_FILE};
+use crate::model::{HoursData, HoursDataFrame, HoursRecord, UserProfile, Users};
+use crate::settings::Settings;
+use crate::storage::StorageKind;
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+/// Copies the data files that failed to load into a `broken-<timestamp>` backup,
+/// so that they survive any later repair or restore.
+pub fn preserve_broken<P: AsRef<Path>>(folder: P) -> Result<PathBuf> {
+    Ok(backup::create(folder, "broken-")?.path)
+}
+
+/// Result of [salvage]: the data that could be read and how many records were dropped.
+#[derive(Debug, Clone)]
+pub struct Salvage {
+    pub data: HoursData,
+    pub dropped: usize,
+}
+
+/// Reads as much as possible from json data files that fail to load as a whole.
+/// Records and journal entries that can not be read are dropped. If the users file
+/// is unreadable, the users are taken from the names file of folders older than version 4,
+/// linking the records by name like [crate::migration] does, or else from the records.
+pub fn salvage<P: AsRef<Path>>(folder: P) -> Result<Salvage> {
+    let folder = folder.as_ref();
+    let file = File::open(folder.join(DATAFRAME_FILE))?;
+    let dataframe: Value = serde_json::from_reader(file)
+        .map_err(|e| anyhow!("{} is not valid json, it can not be salvaged: {}", DATAFRAME_FILE, e))?;
+    let records = dataframe
+        .get("data")
+        .and_then(|d| d.as_array())
+        .ok_or_else(|| anyhow!("{} has no data array", DATAFRAME_FILE))?;
+
+    let mut data = HoursData::default();
+    let mut dropped = 0;
+    for record in records.iter() {
+        match serde_json::from_value::<HoursRecord>(record.clone()) {
+            Ok(record) => data.dataframe.data.push(record),
+            Err(e) => {
+                println!("Dropping record {}: {}", record, e);
+                dropped += 1;
+            }
+        }
+    }
+    if let Some(schema) = dataframe.get("schema") {
+        if let Ok(schema) = serde_json::from_value(schema.clone()) {
+            data.dataframe.schema = schema;
+        }
+    }
+
+    let users = File::open(folder.join(USERS_FILE))
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Ok(serde_json::from_reader::<_, Users>(file)?));
+    let mut names = None;
+    match users {
+        Ok(users) => data.users = users,
+        Err(_) => {
+            names = File::open(folder.join(NAMES_FILE))
+                .ok()
+                .and_then(|file| serde_json::from_reader::<_, Vec<String>>(file).ok());
+            if names.is_none() {
+                data.users = users_from_records(&data.dataframe);
+            }
+        }
+    }
+
+    let mut journal = Vec::new();
+    let journal_path = folder.join(JOURNAL_FILE);
+    if journal_path.exists() {
+        for line in std::fs::read_to_string(journal_path)?.lines() {
+            match serde_json::from_str::<Value>(line) {
+                Ok(entry) => journal.push(entry),
+                Err(e) => {
+                    println!("Dropping journal entry {}: {}", line, e);
+                    dropped += 1;
+                }
+            }
+        }
+    }
+    if let Some(names) = names.as_mut() {
+        journal = migration::fold_name_events(names, journal)?;
+    }
+    for entry in journal {
+        match serde_json::from_value::<JournalEntry>(entry.clone()) {
+            Ok(entry) => entry.event.apply(&mut data),
+            Err(e) => {
+                println!("Dropping journal entry {}: {}", entry, e);
+                dropped += 1;
+            }
+        }
+    }
+
+    if let Some(names) = names {
+        for record in data.dataframe.data.iter_mut() {
+            record.user_id = migration::user_id_for_name(&names, &record.name);
+        }
+        data.users = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| UserProfile::new(i as u64 + 1, name))
+            .collect();
+    }
+    Ok(Salvage { data, dropped })
+}
+
+/// Profiles with only id and name; records without a user id are left unlinked.
+fn users_from_records(dataframe: &HoursDataFrame) -> Users {
+    let mut users: Users = Vec::new();
+    for record in dataframe.data.iter() {
+        if record.user_id != 0 && !users.iter().any(|u| u.id == record.user_id) {
+            users.push(UserProfile::new(record.user_id, &record.name));
+        }
+    }
+    users
+}
+
+/// Salvages the json data in the data folder and saves the result over the broken files.
+pub fn repair(settings: &Settings) -> Result<Salvage> {
+    let folder = &settings.data_folder;
+    if StorageKind::detect(folder) != StorageKind::Json {
+        return Err(anyhow!("Only json data can be repaired, restore a backup instead"));
+    }
+    let mut salvage = salvage(folder)?;
+    salvage.data.dataframe.repair_indices();
+    let storage = StorageKind::Json.storage(settings);
+    storage.save(&salvage.data)?;
+    salvage.data.storage = Some(storage);
+    salvage.data.folder = folder.clone();
+    Ok(salvage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::fixture;
+    use std::io::Write;
+
+    #[test]
+    fn salvage_links_records_by_name_before_version_4() {
+        let dir = fixture("v3");
+        let mut journal = std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.path().join(JOURNAL_FILE))
+            .unwrap();
+        writeln!(journal, "{{broken").unwrap();
+        drop(journal);
+
+        let salvage = salvage(dir.path()).unwrap();
+        assert_eq!(salvage.dropped, 1);
+        let users = salvage
+            .data
+            .users
+            .iter()
+            .map(|u| (u.name.as_str(), u.id))
+            .collect::<Vec<_>>();
+        assert_eq!(users, vec![("Carl", 1), ("Anna", 2)]);
+        let user_ids = salvage
+            .data
+            .dataframe
+            .data
+            .iter()
+            .map(|r| (r.name.as_str(), r.user_id))
+            .collect::<Vec<_>>();
+        assert_eq!(user_ids, vec![("Anna", 2), ("Bob", 0), ("Carl", 1)]);
+    }
+}
@@ -0,0 +1,283 @@
+use crate::model::{Hours, HoursDataFrame, Period};
+use anyhow::Result;
+use chrono::Datelike;
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Worked time of a user in one month, or in the whole year for [YearSummary::total].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MonthSummary {
+    /// Label of the row, e.g. `2023/04` or `Total`.
+    pub label: String,
+    pub hours: Hours,
+    /// Days with a record of more than zero hours.
+    pub days: usize,
+    /// Hours per worked day.
+    pub average: Hours,
+    pub longest: Hours,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct YearSummary {
+    pub year: i32,
+    pub months: Vec<MonthSummary>,
+    pub total: MonthSummary,
+}
+
+fn average(hours: Hours, days: usize) -> Hours {
+    if days == 0 {
+        Hours::default()
+    } else {
+        Hours::from_seconds(hours.seconds() / days as i64)
+    }
+}
+
+/// Summary of January to December of `year` for the user.
+pub fn year_summary(dataframe: &HoursDataFrame, user_id: u64, year: i32) -> YearSummary {
+    let mut months = Vec::new();
+    let mut all_days = BTreeSet::new();
+    let mut longest = Hours::default();
+    let mut period = Period::new(year, 1);
+    while period.year == year {
+        let mut days = BTreeSet::new();
+        let mut month_longest = Hours::default();
+        for record in dataframe.data.iter() {
+            if record.user_id != user_id || record.period() != period {
+                continue;
+            }
+            let hours = record.hours_worked();
+            if hours.seconds() > 0 {
+                days.insert(record.date());
+            }
+            month_longest = month_longest.max(hours);
+        }
+        let hours = dataframe.hours_for_period(user_id, &period);
+        months.push(MonthSummary {
+            label: format!("{}", period),
+            hours,
+            days: days.len(),
+            average: average(hours, days.len()),
+            longest: month_longest,
+        });
+        longest = longest.max(month_longest);
+        all_days.extend(days);
+        period = period.next();
+    }
+    let hours: Hours = months.iter().map(|m| m.hours).sum();
+    YearSummary {
+        year,
+        total: MonthSummary {
+            label: "Total".to_string(),
+            hours,
+            days: all_days.len(),
+            average: average(hours, all_days.len()),
+            longest,
+        },
+        months,
+    }
+}
+
+impl YearSummary {
+    /// Writes the months and the total as csv, with hours as `HH:MM` and as decimal hours.
+    pub fn write_csv<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record([
+            "Name",
+            "Month",
+            "Hours",
+            "Decimal hours",
+            "Days",
+            "Average day",
+            "Longest session",
+        ])?;
+        for month in self.months.iter().chain(std::iter::once(&self.total)) {
+            writer.write_record([
+                name.to_string(),
+                month.label.clone(),
+                month.hours.to_string(),
+                month.hours.to_decimal_string(),
+                month.days.to_string(),
+                month.average.to_string(),
+                month.longest.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Path for an exported file: the downloads folder, or the home folder if there is none.
+/// Existing files are not overwritten, see [free_path].
+pub fn export_path(file_name: &str) -> PathBuf {
+    let folder = dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_default();
+    free_path(&folder, file_name)
+}
+
+/// `file_name` in the folder, or if that exists the first free name with `-1`, `-2`, ...
+/// added before the extension.
+fn free_path(folder: &Path, file_name: &str) -> PathBuf {
+    let path = folder.join(file_name);
+    if !path.exists() {
+        return path;
+    }
+    let (stem, extension) = match file_name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{}", extension)),
+        None => (file_name, String::new()),
+    };
+    (1..)
+        .map(|i| folder.join(format!("{}-{}{}", stem, i, extension)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// Worked time of a user on one day of a month, for the calendar.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Day {
+    pub day: u32,
+    pub hours: Hours,
+    /// Start time of the first record of the day, `HH:MM`; empty if there is none.
+    pub first_start: String,
+    /// End time of the last record of the day, `HH:MM`; empty if it has not ended.
+    pub last_end: String,
+}
+
+/// The days of the month with the hours and the first and last time of the user.
+pub fn month_days(dataframe: &HoursDataFrame, user_id: u64, period: &Period) -> Vec<Day> {
+    let mut days = dataframe
+        .hours_by_day(user_id, period)
+        .into_iter()
+        .enumerate()
+        .map(|(i, hours)| Day {
+            day: i as u32 + 1,
+            hours,
+            ..Day::default()
+        })
+        .collect::<Vec<_>>();
+    let mut records = dataframe
+        .data
+        .iter()
+        .filter(|r| r.user_id == user_id && r.period() == *period)
+        .filter_map(|r| r.start_dt().ok().map(|start| (start, r)))
+        .collect::<Vec<_>>();
+    records.sort_by_key(|(start, _)| *start);
+    for (start, record) in records {
+        if let Some(day) = days.get_mut(start.day() as usize - 1) {
+            if day.first_start.is_empty() {
+                day.first_start = record.start_time();
+            }
+            day.last_end = record.end_time();
+        }
+    }
+    days
+}
+
+/// Shade of a day in the calendar: 0 for no hours, then 1 to 4 for up to 2, 4, 6 and more hours.
+pub fn shade(hours: Hours) -> u8 {
+    match hours.minutes() {
+        m if m <= 0 => 0,
+        m if m <= 2 * 60 => 1,
+        m if m <= 4 * 60 => 2,
+        m if m <= 6 * 60 => 3,
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::HoursRecord;
+
+    fn record(user_id: u64, start: &str, end: &str) -> HoursRecord {
+        let mut record = HoursRecord {
+            user_id,
+            start: start.to_string(),
+            end: end.to_string(),
+            ..HoursRecord::default()
+        };
+        let start = record.start_dt().unwrap();
+        record.year = start.year();
+        record.month = start.month();
+        record
+    }
+
+    /// Anna (1) works 8, 1.5 and 4 hours on two days in April and 6.25 hours in May;
+    /// Bob (2) has a longer record in April.
+    fn dataframe() -> HoursDataFrame {
+        let mut dataframe = HoursDataFrame::new();
+        dataframe.data = vec![
+            record(1, "2023-04-03 08:00:00", "2023-04-03 16:00:00"),
+            record(1, "2023-04-03 17:00:00", "2023-04-03 18:30:00"),
+            record(1, "2023-04-04 08:00:00", "2023-04-04 12:00:00"),
+            record(1, "2023-05-02 09:00:00", "2023-05-02 15:15:00"),
+            record(2, "2023-04-03 06:00:00", "2023-04-03 16:00:00"),
+        ];
+        dataframe
+    }
+
+    #[test]
+    fn summary_of_the_year() {
+        let summary = year_summary(&dataframe(), 1, 2023);
+        assert_eq!(summary.months.len(), 12);
+        assert_eq!(
+            summary.months[0],
+            MonthSummary {
+                label: "2023/01".to_string(),
+                ..MonthSummary::default()
+            }
+        );
+        assert_eq!(
+            summary.months[3],
+            MonthSummary {
+                label: "2023/04".to_string(),
+                hours: Hours::from_minutes(13 * 60 + 30),
+                days: 2,
+                average: Hours::from_minutes(6 * 60 + 45),
+                longest: Hours::from_hours(8.0),
+            }
+        );
+        assert_eq!(summary.months[4].hours, Hours::from_minutes(6 * 60 + 15));
+        assert_eq!(summary.months[4].average, Hours::from_minutes(6 * 60 + 15));
+        assert_eq!(
+            summary.total,
+            MonthSummary {
+                label: "Total".to_string(),
+                hours: Hours::from_minutes(19 * 60 + 45),
+                days: 3,
+                average: Hours::from_minutes(6 * 60 + 35),
+                longest: Hours::from_hours(8.0),
+            }
+        );
+    }
+
+    #[test]
+    fn csv_export() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = free_path(dir.path(), "Anna 2023.csv");
+        assert_eq!(path, dir.path().join("Anna 2023.csv"));
+        year_summary(&dataframe(), 1, 2023)
+            .write_csv("Anna", &path)
+            .unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 14);
+        assert_eq!(
+            lines[0],
+            "Name,Month,Hours,Decimal hours,Days,Average day,Longest session"
+        );
+        assert_eq!(lines[4], "Anna,2023/04,13:30,13.5,2,06:45,08:00");
+        assert_eq!(lines[13], "Anna,Total,19:45,19.75,3,06:35,08:00");
+
+        assert_eq!(
+            free_path(dir.path(), "Anna 2023.csv"),
+            dir.path().join("Anna 2023-1.csv")
+        );
+        std::fs::write(dir.path().join("Anna 2023-1.csv"), "").unwrap();
+        assert_eq!(
+            free_path(dir.path(), "Anna 2023.csv"),
+            dir.path().join("Anna 2023-2.csv")
+        );
+    }
+}
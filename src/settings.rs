@@ -0,0 +1,281 @@
+use crate::backup::{atomic_write, BackupPolicy};
+use crate::journal::COMPACT_AFTER;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use std::path::{Path, PathBuf};
+
+const SETTINGS_FILE: &str = "settings.yaml";
+const ENV_PREFIX: &str = "HOURRS_";
+
+/// Tunables of the app, read from `settings.yaml` in the config folder.
+/// Every setting can be overridden by an environment variable `HOURRS_<NAME>`
+/// or a command line option `--<name>` (with `-` instead of `_`),
+/// e.g. `HOURRS_DATA_FOLDER=/tmp/hours` or `--data-folder /tmp/hours`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub data_folder: PathBuf,
+    pub backup_count: usize,
+    pub backup_interval_minutes: i64,
+    /// Number of journal events after which the json snapshot is rewritten.
+    pub compact_after: usize,
+    /// Minutes without a click or key press after which a login expires.
+    pub admin_timeout_minutes: i64,
+    /// If true, every user needs a PIN to start or end; otherwise only users that have one.
+    pub pin_required: bool,
+    /// Failed PIN entries in a row after which the user is locked out.
+    pub pin_max_failures: u32,
+    pub pin_lockout_minutes: i64,
+    /// Failed admin logins in a row after which logging in is blocked.
+    pub login_max_failures: u32,
+    pub login_lockout_minutes: i64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let backups = BackupPolicy::default();
+        Self {
+            data_folder: dirs::home_dir().unwrap_or_default().join("Hours"),
+            backup_count: backups.count,
+            backup_interval_minutes: backups.interval_minutes,
+            compact_after: COMPACT_AFTER,
+            admin_timeout_minutes: 10,
+            pin_required: false,
+            pin_max_failures: 5,
+            pin_lockout_minutes: 5,
+            login_max_failures: 5,
+            login_lockout_minutes: 15,
+        }
+    }
+}
+
+impl Settings {
+    pub fn backup_policy(&self) -> BackupPolicy {
+        BackupPolicy {
+            count: self.backup_count,
+            interval_minutes: self.backup_interval_minutes,
+        }
+    }
+
+    /// Names and values of all settings, in declaration order.
+    pub fn fields(&self) -> Result<Vec<(String, String)>> {
+        let value = serde_yaml::to_value(self)?;
+        let mapping = value
+            .as_mapping()
+            .ok_or_else(|| anyhow!("Settings are not a mapping"))?;
+        Ok(mapping
+            .iter()
+            .map(|(key, value)| {
+                let key = key.as_str().unwrap_or_default().to_string();
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => serde_yaml::to_string(other)
+                        .unwrap_or_default()
+                        .trim()
+                        .to_string(),
+                };
+                (key, value)
+            })
+            .collect())
+    }
+
+    /// Returns a copy with the given settings replaced; values are parsed as yaml scalars.
+    pub fn with_overrides(&self, overrides: &[(String, String)]) -> Result<Settings> {
+        let mut value = serde_yaml::to_value(self)?;
+        let mapping = value
+            .as_mapping_mut()
+            .ok_or_else(|| anyhow!("Settings are not a mapping"))?;
+        for (key, text) in overrides {
+            let key_value = Value::String(key.clone());
+            let new_value = match mapping.get(&key_value) {
+                Some(Value::String(_)) => Value::String(text.clone()),
+                Some(_) => serde_yaml::from_str(text)
+                    .map_err(|e| anyhow!("Invalid value {} for {}: {}", text, key, e))?,
+                None => return Err(anyhow!("Unknown setting {}", key)),
+            };
+            mapping.insert(key_value, new_value);
+        }
+        let mut settings: Settings = serde_yaml::from_value(value)?;
+        settings.data_folder = expand_home(&settings.data_folder);
+        Ok(settings)
+    }
+}
+
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Settings as stored in the settings file together with the overrides from
+/// the environment and the command line, which are not written back to the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingsStore {
+    pub path: PathBuf,
+    pub file: Settings,
+    pub overrides: Vec<(String, String)>,
+    /// Set by [SettingsStore::load_or_default] when the settings could not be read.
+    /// The data folder is then unknown and no data may be read or written.
+    pub error: Option<String>,
+}
+
+impl SettingsStore {
+    pub fn default_path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_default()
+            .join("hourrs-classic")
+            .join(SETTINGS_FILE)
+    }
+
+    /// Reads the settings file and collects the overrides from the environment and `args`.
+    /// The settings file itself can be chosen with `HOURRS_SETTINGS` or `--settings`.
+    /// `args` start with the program name; an unknown option or one without value is an error.
+    pub fn load(args: &[String]) -> Result<SettingsStore> {
+        let names: Vec<String> = Settings::default()
+            .fields()?
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let mut path = std::env::var(format!("{}SETTINGS", ENV_PREFIX))
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| Self::default_path());
+        let mut overrides = Vec::new();
+        for name in names.iter() {
+            if let Ok(value) = std::env::var(format!("{}{}", ENV_PREFIX, name.to_uppercase())) {
+                overrides.push((name.clone(), value));
+            }
+        }
+        let mut i = 1;
+        while i < args.len() {
+            let arg = &args[i];
+            i += 1;
+            let option = match arg.strip_prefix("--") {
+                Some(option) => option,
+                None => continue,
+            };
+            let setting = names.iter().find(|name| name.replace('_', "-") == option);
+            if setting.is_none() && option != "settings" && option != "convert-to" {
+                return Err(anyhow!("Unknown option {}", arg));
+            }
+            let value = match args.get(i) {
+                Some(value) if !value.starts_with("--") => value,
+                _ => return Err(anyhow!("Option {} needs a value", arg)),
+            };
+            i += 1;
+            match setting {
+                Some(name) => overrides.push((name.clone(), value.clone())),
+                None if option == "settings" => path = PathBuf::from(value),
+                None => {}
+            }
+        }
+
+        let file: Settings = if path.exists() {
+            let text = std::fs::read_to_string(&path)?;
+            serde_yaml::from_str(&text).map_err(|e| anyhow!("Error in {:?}: {}", path, e))?
+        } else {
+            Settings::default()
+        };
+        file.with_overrides(&overrides)
+            .map_err(|e| anyhow!("Error in the settings from the environment or command line: {}", e))?;
+        Ok(SettingsStore {
+            path,
+            file,
+            overrides,
+            error: None,
+        })
+    }
+
+    /// Like [SettingsStore::load], but returns the default settings with the error set
+    /// if loading fails, so that the app can show the error instead of using another data folder.
+    pub fn load_or_default(args: &[String]) -> SettingsStore {
+        Self::load(args).unwrap_or_else(|e| {
+            println!("Error loading settings: {}", e);
+            SettingsStore {
+                path: Self::default_path(),
+                file: Settings::default(),
+                overrides: Vec::new(),
+                error: Some(e.to_string()),
+            }
+        })
+    }
+
+    /// Settings from the file with all overrides applied.
+    pub fn effective(&self) -> Settings {
+        self.file
+            .with_overrides(&self.overrides)
+            .unwrap_or_else(|e| {
+                println!("Error applying settings overrides: {}", e);
+                self.file.clone()
+            })
+    }
+
+    pub fn is_overridden(&self, name: &str) -> bool {
+        self.overrides.iter().any(|(key, _)| key == name)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        atomic_write(&self.path, serde_yaml::to_string(&self.file)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        std::iter::once("hourrs-classic")
+            .chain(args.iter().copied())
+            .map(|arg| arg.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn invalid_settings_are_not_replaced_by_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE);
+        let path_arg = path.to_string_lossy().to_string();
+        std::fs::write(&path, "data_folder: /mnt/shared/Hours\nbackup_count: 5\n").unwrap();
+        let store = SettingsStore::load_or_default(&args(&["--settings", &path_arg]));
+        assert_eq!(store.error, None);
+        assert_eq!(store.effective().data_folder, PathBuf::from("/mnt/shared/Hours"));
+
+        let store = SettingsStore::load_or_default(&args(&[
+            "--settings",
+            &path_arg,
+            "--backup-count",
+            "abc",
+        ]));
+        assert!(store.error.is_some());
+
+        std::fs::write(&path, "data_folder: [/mnt/shared/Hours\n").unwrap();
+        let store = SettingsStore::load_or_default(&args(&["--settings", &path_arg]));
+        assert!(store.error.unwrap().contains(&path_arg));
+    }
+
+    #[test]
+    fn unknown_options_and_missing_values_are_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path_arg = dir.path().join(SETTINGS_FILE).to_string_lossy().to_string();
+        let load = |extra: &[&str]| {
+            let mut all = vec!["--settings", path_arg.as_str()];
+            all.extend_from_slice(extra);
+            SettingsStore::load(&args(&all))
+        };
+        let store = load(&["--backup-count", "5", "--convert-to", "sqlite"]).unwrap();
+        assert_eq!(store.effective().backup_count, 5);
+
+        let error = load(&["--data-foldr", "/tmp/hours"]).unwrap_err();
+        assert!(error.to_string().contains("--data-foldr"));
+        let error = load(&["--data-folder"]).unwrap_err();
+        assert!(error.to_string().contains("needs a value"));
+        let error = load(&["--data-folder", "--backup-count", "5"]).unwrap_err();
+        assert!(error.to_string().contains("--data-folder"));
+        assert!(load(&["--settings"]).is_err());
+    }
+}
@@ -0,0 +1,697 @@
+use crate::backup::{self, BackupPolicy};
+use crate::journal::{self, Event, JournalEntry, HISTORY_FILE, JOURNAL_FILE};
+use crate::lock::{ChangeDetector, FolderLock};
+use crate::model::{HoursData, HoursDataFrame, HoursRecord, UserProfile};
+use crate::settings::Settings;
+use anyhow::{anyhow, Result};
+use rusqlite::{params, Connection};
+
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+pub const SQLITE_FILE: &str = "hours.sqlite3";
+/// Schema version of the sqlite store, kept in `PRAGMA user_version`.
+/// Versions match [crate::migration::FORMAT_VERSION]: 4 replaced the names table by users,
+/// 5 added the archived flag and 6 the PIN hash of users.
+const SQLITE_VERSION: i64 = 6;
+
+/// Persistence of [HoursData].
+/// Backends only have to implement a full `load`/`save`; the incremental methods
+/// default to a full save and can be overridden where the backend supports it.
+/// Saving fails with [crate::lock::ExternalModification] if another process
+/// changed the data since this process loaded or saved it.
+pub trait Storage: Debug {
+    /// Data folder of the store.
+    fn folder(&self) -> PathBuf;
+    /// True if the store already holds data.
+    fn exists(&self) -> bool;
+    fn load(&self) -> Result<HoursData>;
+    fn save(&self, data: &HoursData) -> Result<()>;
+
+    /// Stores a single change; `data` is the state after the event has been applied.
+    fn record(&self, data: &HoursData, _event: &Event) -> Result<()> {
+        self.save(data)
+    }
+
+    /// Recorded events, oldest first; empty if the backend keeps no history.
+    fn history(&self) -> Result<Vec<JournalEntry>> {
+        Ok(Vec::new())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    Json,
+    Sqlite,
+}
+
+impl StorageKind {
+    pub fn parse(kind: &str) -> Result<StorageKind> {
+        match kind.to_lowercase().as_str() {
+            "json" => Ok(StorageKind::Json),
+            "sqlite" | "sqlite3" => Ok(StorageKind::Sqlite),
+            _ => Err(anyhow!("Unknown storage backend {}", kind)),
+        }
+    }
+
+    /// Storage in the data folder of `settings`.
+    pub fn storage(&self, settings: &Settings) -> Rc<dyn Storage> {
+        let folder = &settings.data_folder;
+        match self {
+            StorageKind::Json => {
+                let mut storage = JsonStorage::new(folder, settings.backup_policy());
+                storage.compact_after = settings.compact_after;
+                Rc::new(storage)
+            }
+            StorageKind::Sqlite => Rc::new(SqliteStorage::new(
+                folder.join(SQLITE_FILE),
+                settings.backup_policy(),
+            )),
+        }
+    }
+
+    /// Backend used by the data folder: SQLite if the database exists, json otherwise.
+    pub fn detect<P: AsRef<Path>>(folder: P) -> StorageKind {
+        if folder.as_ref().join(SQLITE_FILE).exists() {
+            StorageKind::Sqlite
+        } else {
+            StorageKind::Json
+        }
+    }
+}
+
+/// Opens the storage of the data folder, creating the folder and an empty store if needed.
+pub fn open(settings: &Settings) -> Result<Rc<dyn Storage>> {
+    let folder = &settings.data_folder;
+    if !folder.is_dir() {
+        std::fs::create_dir_all(folder)?;
+    }
+    let storage = StorageKind::detect(folder).storage(settings);
+    if !storage.exists() {
+        storage.save(&HoursData::default())?;
+    }
+    Ok(storage)
+}
+
+/// Copies all data from one backend into another.
+/// Records sharing an index, which older versions could create, get new indices first.
+pub fn convert(from: &dyn Storage, to: &dyn Storage) -> Result<()> {
+    let mut data = from.load()?;
+    let repaired = data.dataframe.repair_indices();
+    if repaired > 0 {
+        println!("Repairing {} records with duplicate index", repaired);
+    }
+    to.save(&data)
+}
+
+/// Converts the data folder to the given backend.
+/// Converting back to json moves the SQLite database aside, so that json is detected again.
+pub fn convert_folder(settings: &Settings, to: StorageKind) -> Result<()> {
+    let folder = &settings.data_folder;
+    let from = StorageKind::detect(folder);
+    if from == to {
+        return Err(anyhow!("Data in {:?} already uses {:?} storage", folder, to));
+    }
+    backup::create(folder, "")?;
+    convert(
+        from.storage(settings).as_ref(),
+        to.storage(settings).as_ref(),
+    )?;
+    if from == StorageKind::Sqlite {
+        let sqlite_path = folder.join(SQLITE_FILE);
+        let mut converted = sqlite_path.clone().into_os_string();
+        converted.push(format!(".{}", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+        std::fs::rename(&sqlite_path, converted)?;
+    }
+    Ok(())
+}
+
+/// The original layout: a snapshot in `hours_dataframe.json` and `hours_users.json`
+/// in the data folder, plus a journal of the events since the snapshot.
+/// The snapshot is rewritten once the journal reaches `compact_after` events.
+#[derive(Debug, Clone)]
+pub struct JsonStorage {
+    pub folder: PathBuf,
+    pub policy: BackupPolicy,
+    pub compact_after: usize,
+    changes: ChangeDetector,
+}
+
+impl JsonStorage {
+    pub fn new<P: AsRef<Path>>(folder: P, policy: BackupPolicy) -> Self {
+        Self {
+            folder: folder.as_ref().to_path_buf(),
+            policy,
+            compact_after: journal::COMPACT_AFTER,
+            changes: ChangeDetector::default(),
+        }
+    }
+
+    /// Writes the snapshot and moves the journal to the history.
+    fn compact(&self, data: &HoursData) -> Result<()> {
+        data.save_to(&self.folder)?;
+        journal::archive(&self.folder)
+    }
+}
+
+impl Storage for JsonStorage {
+    fn folder(&self) -> PathBuf {
+        self.folder.clone()
+    }
+
+    fn exists(&self) -> bool {
+        self.folder.join(crate::migration::DATAFRAME_FILE).exists()
+    }
+
+    fn load(&self) -> Result<HoursData> {
+        let _lock = FolderLock::acquire(&self.folder)?;
+        let mut data = HoursData::from_store(&self.folder)?;
+        for entry in journal::read(self.folder.join(JOURNAL_FILE))? {
+            entry.event.apply(&mut data);
+        }
+        self.changes.remember(&self.folder)?;
+        Ok(data)
+    }
+
+    fn save(&self, data: &HoursData) -> Result<()> {
+        let _lock = FolderLock::acquire(&self.folder)?;
+        self.changes.check(&self.folder)?;
+        backup::backup_if_due(&self.folder, &self.policy)?;
+        self.compact(data)?;
+        self.changes.remember(&self.folder)
+    }
+
+    fn record(&self, data: &HoursData, event: &Event) -> Result<()> {
+        let _lock = FolderLock::acquire(&self.folder)?;
+        self.changes.check(&self.folder)?;
+        backup::backup_if_due(&self.folder, &self.policy)?;
+        let journal_path = self.folder.join(JOURNAL_FILE);
+        journal::append(&journal_path, &[JournalEntry::new(event.clone())])?;
+        if journal::read(&journal_path)?.len() >= self.compact_after {
+            self.compact(data)?;
+        }
+        self.changes.remember(&self.folder)
+    }
+
+    fn history(&self) -> Result<Vec<JournalEntry>> {
+        let mut entries = journal::read_lenient(self.folder.join(HISTORY_FILE))?;
+        entries.extend(journal::read_lenient(self.folder.join(JOURNAL_FILE))?);
+        Ok(entries)
+    }
+}
+
+/// Embedded SQLite database; records are inserted and updated one by one.
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    pub path: PathBuf,
+    pub policy: BackupPolicy,
+    changes: ChangeDetector,
+}
+
+impl SqliteStorage {
+    pub fn new<P: AsRef<Path>>(path: P, policy: BackupPolicy) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            policy,
+            changes: ChangeDetector::default(),
+        }
+    }
+
+    /// Locks the folder, checks for external changes and takes a backup if due.
+    /// The returned lock has to be held until the write is finished.
+    fn prepare_write(&self) -> Result<FolderLock> {
+        let folder = self.folder();
+        let lock = FolderLock::acquire(&folder)?;
+        self.changes.check(&folder)?;
+        backup::backup_if_due(&folder, &self.policy)?;
+        Ok(lock)
+    }
+
+    fn connect(&self) -> Result<Connection> {
+        let mut connection = Connection::open(&self.path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS records (
+                record_index INTEGER PRIMARY KEY,
+                record_rowid INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                year INTEGER NOT NULL,
+                month INTEGER NOT NULL,
+                start_time TEXT NOT NULL,
+                end_time TEXT NOT NULL,
+                hours TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS users (
+                id INTEGER PRIMARY KEY,
+                position INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                employee_number TEXT NOT NULL,
+                email TEXT NOT NULL,
+                team TEXT NOT NULL,
+                contract TEXT NOT NULL,
+                weekly_hours REAL NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS counters (
+                name TEXT PRIMARY KEY,
+                value INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                time TEXT NOT NULL,
+                event TEXT NOT NULL
+            );",
+        )?;
+        let version: i64 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < SQLITE_VERSION {
+            Self::upgrade(&mut connection, version)?;
+        }
+        Ok(connection)
+    }
+
+    /// Brings tables created in their first layout (or by an older version) up to date.
+    fn upgrade(connection: &mut Connection, version: i64) -> Result<()> {
+        if version < 4 {
+            Self::upgrade_to_users(connection)?;
+        }
+        let transaction = connection.transaction()?;
+        if version < 5 {
+            transaction.execute(
+                "ALTER TABLE users ADD COLUMN archived INTEGER NOT NULL DEFAULT 0",
+                [],
+            )?;
+        }
+        if version < 6 {
+            transaction.execute(
+                "ALTER TABLE users ADD COLUMN pin_hash TEXT NOT NULL DEFAULT ''",
+                [],
+            )?;
+        }
+        transaction.execute_batch(&format!("PRAGMA user_version = {}", SQLITE_VERSION))?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Adds the `user_id` column to the records and moves the names table into users,
+    /// linking records to users by name like [crate::migration] does for json.
+    fn upgrade_to_users(connection: &mut Connection) -> Result<()> {
+        let has_names: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'names'",
+            [],
+            |row| row.get(0),
+        )?;
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "ALTER TABLE records ADD COLUMN user_id INTEGER NOT NULL DEFAULT 0",
+            [],
+        )?;
+        if has_names {
+            transaction.execute_batch(
+                "INSERT INTO users
+                    (id, position, name, employee_number, email, team, contract, weekly_hours)
+                    SELECT position + 1, position, name, '', '', '', '', 0 FROM names;
+                UPDATE records SET user_id = COALESCE(
+                    (SELECT MIN(id) FROM users WHERE users.name = records.name), 0);
+                DROP TABLE names;",
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Inserts a new record; fails if a record with the same index exists.
+    fn insert_record(connection: &Connection, record: &HoursRecord) -> Result<()> {
+        Self::write_record(connection, "INSERT", record)
+    }
+
+    /// Inserts a record or replaces the one with the same index.
+    fn upsert_record(connection: &Connection, record: &HoursRecord) -> Result<()> {
+        Self::write_record(connection, "INSERT OR REPLACE", record)
+    }
+
+    fn write_record(connection: &Connection, insert: &str, record: &HoursRecord) -> Result<()> {
+        connection.execute(
+            &format!(
+                "{} INTO records
+                (record_index, record_rowid, name, user_id, year, month, start_time, end_time, hours)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                insert
+            ),
+            params![
+                record.index as i64,
+                record.rowid as i64,
+                record.name,
+                record.user_id as i64,
+                record.year,
+                record.month,
+                record.start,
+                record.end,
+                record.hours
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn set_next_index(connection: &Connection, next_index: isize) -> Result<()> {
+        connection.execute(
+            "INSERT OR REPLACE INTO counters (name, value) VALUES ('next_index', ?1)",
+            params![next_index as i64],
+        )?;
+        Ok(())
+    }
+
+    fn insert_users(connection: &Connection, users: &[UserProfile]) -> Result<()> {
+        connection.execute("DELETE FROM users", [])?;
+        for (i, user) in users.iter().enumerate() {
+            connection.execute(
+                "INSERT INTO users
+                    (id, position, name, employee_number, email, team, contract, weekly_hours,
+                        archived, pin_hash)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    user.id as i64,
+                    i as i64,
+                    user.name,
+                    user.employee_number,
+                    user.email,
+                    user.team,
+                    user.contract,
+                    user.weekly_hours,
+                    user.archived,
+                    user.pin_hash
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn folder(&self) -> PathBuf {
+        self.path
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default()
+    }
+
+    fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn load(&self) -> Result<HoursData> {
+        println!("Load from {:?}", self.path);
+        let _lock = FolderLock::acquire(self.folder())?;
+        let connection = self.connect()?;
+        let mut statement = connection.prepare(
+            "SELECT record_index, record_rowid, name, user_id, year, month, start_time, end_time, hours
+                FROM records ORDER BY record_index",
+        )?;
+        let data = statement
+            .query_map([], |row| {
+                Ok(HoursRecord::new(
+                    row.get::<_, i64>(0)? as isize,
+                    row.get::<_, i64>(1)? as isize,
+                    row.get(2)?,
+                    row.get::<_, i64>(3)? as u64,
+                    row.get(4)?,
+                    row.get(5)?,
+                    row.get(6)?,
+                    row.get(7)?,
+                    row.get(8)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<HoursRecord>>>()?;
+        let mut statement = connection.prepare(
+            "SELECT id, name, employee_number, email, team, contract, weekly_hours, archived,
+                pin_hash FROM users ORDER BY position",
+        )?;
+        let users = statement
+            .query_map([], |row| {
+                Ok(UserProfile {
+                    id: row.get::<_, i64>(0)? as u64,
+                    name: row.get(1)?,
+                    employee_number: row.get(2)?,
+                    email: row.get(3)?,
+                    team: row.get(4)?,
+                    contract: row.get(5)?,
+                    weekly_hours: row.get(6)?,
+                    archived: row.get(7)?,
+                    pin_hash: row.get(8)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<UserProfile>>>()?;
+
+        let next_index: i64 = connection
+            .query_row(
+                "SELECT value FROM counters WHERE name = 'next_index'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+
+        self.changes.remember(self.folder())?;
+
+        let mut dataframe = HoursDataFrame::new();
+        dataframe.data = data;
+        dataframe.next_index = next_index as isize;
+        Ok(HoursData {
+            dataframe,
+            users,
+            ..HoursData::default()
+        })
+    }
+
+    fn save(&self, data: &HoursData) -> Result<()> {
+        println!("Saving to {:?}", self.path);
+        let _lock = self.prepare_write()?;
+        let mut connection = self.connect()?;
+        let transaction = connection.transaction()?;
+        transaction.execute("DELETE FROM records", [])?;
+        for record in data.dataframe.data.iter() {
+            Self::insert_record(&transaction, record)?;
+        }
+        Self::set_next_index(&transaction, data.dataframe.next_index)?;
+        Self::insert_users(&transaction, &data.users)?;
+        transaction.commit()?;
+        drop(connection);
+        self.changes.remember(self.folder())
+    }
+
+    fn record(&self, data: &HoursData, event: &Event) -> Result<()> {
+        let _lock = self.prepare_write()?;
+        let mut connection = self.connect()?;
+        match event {
+            Event::Started { record } | Event::Ended { record } | Event::Edited { record } => {
+                Self::upsert_record(&connection, record)?;
+                Self::set_next_index(&connection, data.dataframe.next_index)?;
+            }
+            Event::UserRenamed { id, to, .. } => {
+                let transaction = connection.transaction()?;
+                transaction.execute(
+                    "UPDATE users SET name = ?1 WHERE id = ?2",
+                    params![to, *id as i64],
+                )?;
+                transaction.execute(
+                    "UPDATE records SET name = ?1 WHERE user_id = ?2",
+                    params![to, *id as i64],
+                )?;
+                transaction.commit()?;
+            }
+            Event::RecordsReassigned {
+                from_user_id,
+                from_name,
+                user_id,
+                name,
+            } => {
+                connection.execute(
+                    "UPDATE records SET user_id = ?1, name = ?2 WHERE user_id = ?3 AND name = ?4",
+                    params![*user_id as i64, name, *from_user_id as i64, from_name],
+                )?;
+            }
+            Event::UserAdded { .. }
+            | Event::UserUpdated { .. }
+            | Event::UserRemoved { .. }
+            | Event::UsersReordered { .. } => {
+                let transaction = connection.transaction()?;
+                Self::insert_users(&transaction, &data.users)?;
+                transaction.commit()?;
+            }
+        }
+        let entry = JournalEntry::new(event.clone());
+        connection.execute(
+            "INSERT INTO history (time, event) VALUES (?1, ?2)",
+            params![entry.time, serde_json::to_string(&entry.event)?],
+        )?;
+        drop(connection);
+        self.changes.remember(self.folder())
+    }
+
+    fn history(&self) -> Result<Vec<JournalEntry>> {
+        let connection = self.connect()?;
+        let mut statement = connection.prepare("SELECT time, event FROM history ORDER BY id")?;
+        let rows = statement.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let (time, event) = row?;
+            if let Ok(event) = serde_json::from_str(&event) {
+                entries.push(JournalEntry { time, event });
+            }
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{assert_same, data_folder};
+
+    fn record(index: isize, name: &str, user_id: u64) -> HoursRecord {
+        HoursRecord::new(
+            index,
+            index,
+            name.to_string(),
+            user_id,
+            2023,
+            4,
+            format!("2023-04-{:02} 08:00:00", index + 1),
+            format!("2023-04-{:02} 16:00:00", index + 1),
+            "".to_string(),
+        )
+    }
+
+    fn sample() -> HoursData {
+        let mut data = HoursData {
+            users: vec![
+                UserProfile {
+                    team: "A".to_string(),
+                    weekly_hours: 40.0,
+                    pin_hash: "hash".to_string(),
+                    ..UserProfile::new(1, "Anna")
+                },
+                UserProfile {
+                    archived: true,
+                    ..UserProfile::new(3, "Bob")
+                },
+            ],
+            ..HoursData::default()
+        };
+        data.dataframe.data = vec![record(0, "Anna", 1), record(4, "Bob", 3)];
+        data.dataframe.next_index = 10;
+        data
+    }
+
+    fn user_version(path: &Path) -> i64 {
+        Connection::open(path)
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn fresh_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SQLITE_FILE);
+        let storage = SqliteStorage::new(&path, BackupPolicy::default());
+        assert!(!storage.exists());
+        let data = storage.load().unwrap();
+        assert!(data.dataframe.data.is_empty() && data.users.is_empty());
+        assert_eq!(user_version(&path), SQLITE_VERSION);
+
+        storage.save(&sample()).unwrap();
+        let loaded = SqliteStorage::new(&path, BackupPolicy::default()).load().unwrap();
+        assert_same(&loaded, &sample());
+    }
+
+    #[test]
+    fn database_with_names_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SQLITE_FILE);
+        {
+            let connection = Connection::open(&path).unwrap();
+            connection
+                .execute_batch(
+                    "CREATE TABLE records (
+                        record_index INTEGER PRIMARY KEY,
+                        record_rowid INTEGER NOT NULL,
+                        name TEXT NOT NULL,
+                        year INTEGER NOT NULL,
+                        month INTEGER NOT NULL,
+                        start_time TEXT NOT NULL,
+                        end_time TEXT NOT NULL,
+                        hours TEXT NOT NULL
+                    );
+                    CREATE TABLE names (
+                        position INTEGER PRIMARY KEY,
+                        name TEXT NOT NULL
+                    );
+                    INSERT INTO names VALUES (0, 'Anna'), (1, 'Bob');
+                    INSERT INTO records VALUES
+                        (0, 0, 'Anna', 2023, 4, '2023-04-01 08:00:00', '2023-04-01 16:00:00', ''),
+                        (1, 1, 'Bob', 2023, 4, '2023-04-02 08:00:00', '', ''),
+                        (2, 2, 'Zed', 2023, 4, '2023-04-03 08:00:00', '', '7.5');",
+                )
+                .unwrap();
+        }
+        let data = SqliteStorage::new(&path, BackupPolicy::default()).load().unwrap();
+        assert_eq!(user_version(&path), SQLITE_VERSION);
+        let users = data
+            .users
+            .iter()
+            .map(|u| (u.id, u.name.as_str(), u.archived, u.pin_hash.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(users, vec![(1, "Anna", false, ""), (2, "Bob", false, "")]);
+        let user_ids = data
+            .dataframe
+            .data
+            .iter()
+            .map(|r| (r.name.as_str(), r.user_id))
+            .collect::<Vec<_>>();
+        assert_eq!(user_ids, vec![("Anna", 1), ("Bob", 2), ("Zed", 0)]);
+        assert_eq!(data.dataframe.data[2].hours, "7.5");
+        let has_names: bool = Connection::open(&path)
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'names'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(!has_names);
+    }
+
+    #[test]
+    fn convert_folder_round_trip() {
+        let (dir, settings) = data_folder();
+        StorageKind::Json.storage(&settings).save(&sample()).unwrap();
+
+        convert_folder(&settings, StorageKind::Sqlite).unwrap();
+        assert_eq!(StorageKind::detect(dir.path()), StorageKind::Sqlite);
+        assert_same(&StorageKind::Sqlite.storage(&settings).load().unwrap(), &sample());
+        assert!(convert_folder(&settings, StorageKind::Sqlite).is_err());
+
+        std::fs::remove_file(dir.path().join(crate::migration::DATAFRAME_FILE)).unwrap();
+        convert_folder(&settings, StorageKind::Json).unwrap();
+        assert_eq!(StorageKind::detect(dir.path()), StorageKind::Json);
+        assert_same(&StorageKind::Json.storage(&settings).load().unwrap(), &sample());
+    }
+
+    #[test]
+    fn convert_gives_duplicate_indices_new_ones() {
+        let (dir, settings) = data_folder();
+        let mut data = sample();
+        data.dataframe.data.push(record(4, "Anna", 1));
+        StorageKind::Json.storage(&settings).save(&data).unwrap();
+        let sqlite = SqliteStorage::new(dir.path().join("other.sqlite"), BackupPolicy::default());
+        assert!(sqlite.save(&data).is_err());
+
+        convert_folder(&settings, StorageKind::Sqlite).unwrap();
+        let loaded = StorageKind::Sqlite.storage(&settings).load().unwrap();
+        let mut indices: Vec<isize> = loaded.dataframe.data.iter().map(|r| r.index).collect();
+        indices.sort();
+        assert_eq!(indices, vec![0, 4, 10]);
+        assert_eq!(loaded.dataframe.next_index, 11);
+        assert_eq!(loaded.dataframe.data.len(), data.dataframe.data.len());
+    }
+}
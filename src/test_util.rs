@@ -0,0 +1,47 @@
+//! Helpers shared by the unit tests.
+
+use crate::auth::{Access, Role};
+use crate::model::HoursData;
+use crate::settings::Settings;
+
+use std::path::Path;
+
+pub fn admin() -> Access {
+    Access {
+        name: "admin".to_string(),
+        role: Role::Admin,
+        team: String::new(),
+    }
+}
+
+/// Empty temporary data folder and settings using it; the folder is removed with the [tempfile::TempDir].
+pub fn data_folder() -> (tempfile::TempDir, Settings) {
+    let dir = tempfile::tempdir().unwrap();
+    let settings = Settings {
+        data_folder: dir.path().to_path_buf(),
+        ..Settings::default()
+    };
+    (dir, settings)
+}
+
+/// Copy of the fixture folder of a format version in a temporary folder.
+/// There are fixtures for the versions whose migration changes data (1 to 4)
+/// and for the current version.
+pub fn fixture(version: &str) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(version);
+    for entry in std::fs::read_dir(source).unwrap() {
+        let entry = entry.unwrap();
+        std::fs::copy(entry.path(), dir.path().join(entry.file_name())).unwrap();
+    }
+    dir
+}
+
+/// Compares the stored parts of the data: records, the next index and users.
+pub fn assert_same(data: &HoursData, expected: &HoursData) {
+    assert_eq!(data.dataframe.data, expected.dataframe.data);
+    assert_eq!(data.dataframe.next_index, expected.dataframe.next_index);
+    assert_eq!(data.users, expected.users);
+}